@@ -1,7 +1,10 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{
+    ed25519_program, hash::hash, instruction::Instruction, sysvar::instructions as ix_sysvar,
+};
 use anchor_spl::{
     associated_token::AssociatedToken,
-    token::{self, Mint, Token, TokenAccount, Transfer},
+    token::{self, Burn, Mint, MintTo, Token, TokenAccount, Transfer},
 };
 
 declare_id!("EWN15wnvd4xHtN9j32zDVQm8zyb2nwy1JHXxaPvFimVM");
@@ -12,11 +15,93 @@ const MAX_BISECT_ITERS: usize = 60;
 const PRICE_MILLI_SCALER: i64 = 1_000;
 const SEED_MARKET: &[u8] = b"market";
 const SEED_POSITION: &[u8] = b"position";
+const SEED_CONFIG: &[u8] = b"config";
+const SEED_VOLUME: &[u8] = b"volume";
+const SEED_LP_MINT: &[u8] = b"lp_mint";
+const SEED_SPONSORSHIP: &[u8] = b"sponsorship";
+const SEED_VOTES: &[u8] = b"votes";
+const SEED_SETTLEMENT: &[u8] = b"settlement";
+const MAX_EVIDENCE_URI_LEN: usize = 200;
+const MAX_ORACLES: usize = 7;
+const MIN_B_FP: i128 = 10_000;
+const MAX_B_FP: i128 = 1_000_000_000_000;
+const MAX_B_SCHEDULE_POINTS: usize = 8;
+const SECS_PER_DAY: i64 = 86_400;
+const VOLUME_WINDOW_DAYS: usize = 30;
+const MAX_FEE_TIERS: usize = 4;
+const EARLY_RESOLUTION_DELAY_SECS: i64 = 6 * 3_600;
+const ORACLE_UPDATE_NOTICE_SECS: i64 = 2 * SECS_PER_DAY;
+const MIN_SETTLEMENT_TIMEOUT_SECS: i64 = 7 * SECS_PER_DAY;
+// Pyth oracle program (FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH), owner of price accounts
+const PYTH_PROGRAM_ID: Pubkey = Pubkey::new_from_array([
+    220, 229, 235, 225, 228, 156, 59, 159, 17, 76, 181, 84, 76, 80, 169, 158, 192, 214, 146, 214, 63, 86,
+    121, 90, 224, 41, 172, 131, 217, 234, 139, 226,
+]);
+// Pyth v2 price account layout
+const PYTH_MAGIC: u32 = 0xa1b2_c3d4;
+const PYTH_EXPO_OFFSET: usize = 20;
+const PYTH_TIMESTAMP_OFFSET: usize = 96;
+const PYTH_AGG_PRICE_OFFSET: usize = 208;
+const PYTH_AGG_CONF_OFFSET: usize = 216;
+const PYTH_AGG_STATUS_OFFSET: usize = 224;
+const PYTH_STATUS_TRADING: u32 = 1;
 
 #[program]
 pub mod milestone_amm {
     use super::*;
 
+    /// Create the protocol-wide config PDA (program upgrade authority only); the signer
+    /// becomes its admin.
+    pub fn init_config(ctx: Context<InitConfig>, params: ConfigParams) -> Result<()> {
+        require!(params.referral_fee_bps <= 10_000, AmmError::InvalidFee);
+        validate_fee_split(params.fee_split)?;
+        validate_fee_tiers(&params.fee_tiers)?;
+        let c = &mut ctx.accounts.config;
+        c.admin = ctx.accounts.admin.key();
+        c.bump = ctx.bumps.config;
+        c.referral_fee_bps = params.referral_fee_bps;
+        c.fee_split = params.fee_split;
+        c.fee_tiers = params.fee_tiers;
+        Ok(())
+    }
+
+    /// Replace the protocol-wide defaults (config admin only).
+    pub fn update_config(ctx: Context<UpdateConfig>, params: ConfigParams) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.config.admin, AmmError::Unauthorized);
+        require!(params.referral_fee_bps <= 10_000, AmmError::InvalidFee);
+        validate_fee_split(params.fee_split)?;
+        validate_fee_tiers(&params.fee_tiers)?;
+        let c = &mut ctx.accounts.config;
+        c.referral_fee_bps = params.referral_fee_bps;
+        c.fee_split = params.fee_split;
+        c.fee_tiers = params.fee_tiers;
+        Ok(())
+    }
+
+    /// Config admin: count (or stop counting) a market's trades toward fee-tier volume.
+    /// Only approved markets with a nonzero `fee_bps` record volume, so tiers cannot be
+    /// farmed by wash trading on a fee-free market anyone can create.
+    pub fn admin_set_volume_tracking(ctx: Context<SetVolumeTracking>, tracked: bool) -> Result<()> {
+        require!(ctx.accounts.admin.key() == ctx.accounts.config.admin, AmmError::Unauthorized);
+        let m = &mut ctx.accounts.market;
+        m.volume_tracked = tracked;
+        emit!(VolumeTrackingSet {
+            market: m.key(),
+            tracked,
+        });
+        Ok(())
+    }
+
+    /// Create the caller's volume account used for fee-tier discounts.
+    pub fn init_trader_volume(ctx: Context<InitTraderVolume>) -> Result<()> {
+        let v = &mut ctx.accounts.trader_volume;
+        v.owner = ctx.accounts.user.key();
+        v.bump = ctx.bumps.trader_volume;
+        v.last_day = Clock::get()?.unix_timestamp / SECS_PER_DAY;
+        v.daily_volume_fp = [0; VOLUME_WINDOW_DAYS];
+        Ok(())
+    }
+
     /// Initialize a market and its vault ATA (owned by the market PDA).
     pub fn init_market(
         ctx: Context<InitMarket>,
@@ -25,6 +110,25 @@ pub mod milestone_amm {
     ) -> Result<()> {
         require!(params.b_fp >= 10_000 && params.b_fp <= 1_000_000_000_000, AmmError::InvalidB);
         require!(params.fee_bps <= 10_000, AmmError::InvalidFee);
+        validate_fee_model(params.fee_model, params.fee_bps)?;
+        require!(params.settlement_timeout_secs >= MIN_SETTLEMENT_TIMEOUT_SECS, AmmError::InvalidUpdate);
+        if let VoidRefund::FixedSplit { hit_fp } = params.void_refund {
+            require!((hit_fp as i128) <= FP_SCALER, AmmError::InvalidUpdate);
+        }
+        // A custom split may move creator/LP shares around but never below the protocol's cut
+        let fee_split = match (params.fee_split, ctx.accounts.config.as_deref()) {
+            (Some(split), Some(config)) => {
+                require!(
+                    split.protocol_bps >= config.fee_split.protocol_bps,
+                    AmmError::InvalidFeeSplit
+                );
+                split
+            }
+            (Some(_), None) => return err!(AmmError::InvalidFeeSplit),
+            (None, Some(config)) => config.fee_split,
+            (None, None) => FeeSplit::PROTOCOL_ONLY,
+        };
+        validate_fee_split(fee_split)?;
         require!(params.deadline_ts > Clock::get()?.unix_timestamp, AmmError::AfterDeadline);
 
         let m = &mut ctx.accounts.market;
@@ -34,6 +138,7 @@ pub mod milestone_amm {
         m.vault_usdc = ctx.accounts.vault_usdc.key();
         m.b_fp = params.b_fp as i128;
         m.fee_bps = params.fee_bps;
+        m.fee_model = params.fee_model;
         m.deadline_ts = params.deadline_ts;
         m.grace_period_secs = params.grace_period_secs;
         m.outcome = Outcome::Unresolved;
@@ -42,10 +147,54 @@ pub mod milestone_amm {
         m.paused = false;
         m.max_trade_usdc_fp = params.max_trade_usdc_fp as i128;
         m.max_position_shares_fp = params.max_position_shares_fp as i128;
+        m.max_sell_shares_fp = params.max_sell_shares_fp as i128;
+        m.max_sell_usdc_fp = params.max_sell_usdc_fp as i128;
+        m.max_side_shares_fp = 0;
+        m.max_imbalance_fp = 0;
+        m.min_trade_interval_secs = 0;
+        m.max_trades_per_window = 0;
+        m.trade_window_secs = 0;
         m.treasury = params.treasury;
         m.milestone_id = milestone_id;
         m.liquidity_usdc_fp = 0;
         m.oracle_signer = None;
+        m.referral_fee_bps = None;
+        m.accrued_fees_fp = 0;
+        m.fee_split = fee_split;
+        m.creator_fees_fp = 0;
+        m.lp_fees_fp = 0;
+        m.lp_mint = ctx.accounts.lp_mint.key();
+        m.lp_liquidity_fp = 0;
+        m.hit_shares_outstanding_fp = 0;
+        m.miss_shares_outstanding_fp = 0;
+        m.residual_snapshotted = false;
+        m.void_payout_ratio_fp = FP_SCALER;
+        m.lp_pool_fp = 0;
+        m.authority_residual_fp = 0;
+        m.residual_recipient = None;
+        m.sponsor_liquidity_fp = 0;
+        m.sponsor_unclaimed_fp = 0;
+        m.sponsor_pool_fp = 0;
+        m.auto_b = params.auto_b;
+        m.b_schedule = Vec::new();
+        m.oracles = Vec::new();
+        m.oracle_threshold = 0;
+        m.settlement_bond_fp = 0;
+        m.challenge_window_secs = 0;
+        m.proposal = None;
+        m.bond_escrow_fp = 0;
+        m.void_refund = params.void_refund;
+        m.cost_basis_outstanding_fp = 0;
+        m.hit_payout_fp = 0;
+        m.attestation_nonce = 0;
+        m.settlement_timeout_secs = params.settlement_timeout_secs;
+        m.force_voided = false;
+        m.early_resolution = false;
+        m.early_resolution_ts = 0;
+        m.early_outcome = Outcome::Unresolved;
+        m.pending_oracle_update = None;
+        m.feed_resolver = None;
+        m.volume_tracked = false;
 
         emit!(MarketInitialized {
             market: m.key(),
@@ -63,6 +212,7 @@ pub mod milestone_amm {
         let market_auth = ctx.accounts.market.authority;
         require!(ctx.accounts.authority.key() == market_auth, AmmError::Unauthorized);
         require!(!paused, AmmError::Paused);
+        require!(!ctx.accounts.market.early_resolution, AmmError::EarlyResolutionPending);
 
         // Transfer USDC from authority ATA to vault
         token::transfer(
@@ -78,11 +228,258 @@ pub mod milestone_amm {
         )?;
 
         // Now mutate market
+        ctx.accounts.vault_usdc.reload()?;
+        let m = &mut ctx.accounts.market;
+        m.liquidity_usdc_fp = m
+            .liquidity_usdc_fp
+            .checked_add(usdc_amount_fp as i128)
+            .ok_or(AmmError::MathOverflow)?;
+        apply_auto_b(m, ctx.accounts.vault_usdc.amount)?;
+        Ok(())
+    }
+
+    /// Add USDC subsidy from any wallet; mints LP tokens 1:1 (market PDA is mint authority).
+    pub fn add_liquidity(ctx: Context<AddLiquidity>, usdc_amount_fp: u64) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(!ctx.accounts.market.paused, AmmError::Paused);
+        require!(ctx.accounts.market.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!ctx.accounts.market.early_resolution, AmmError::EarlyResolutionPending);
+        require!(clock.unix_timestamp < ctx.accounts.market.deadline_ts, AmmError::AfterDeadline);
+        require!(usdc_amount_fp > 0, AmmError::InvalidAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.provider_usdc.to_account_info(),
+                    to: ctx.accounts.vault_usdc.to_account_info(),
+                    authority: ctx.accounts.provider.to_account_info(),
+                },
+            ),
+            usdc_amount_fp,
+        )?;
+
+        let pda_authority = ctx.accounts.market.authority;
+        let bump = ctx.accounts.market.bump;
+        let milestone_id = ctx.accounts.market.milestone_id.clone();
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        token::mint_to(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                MintTo {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    to: ctx.accounts.provider_lp.to_account_info(),
+                    authority: ctx.accounts.market.to_account_info(),
+                },
+                &[&seeds],
+            ),
+            usdc_amount_fp,
+        )?;
+
+        ctx.accounts.vault_usdc.reload()?;
         let m = &mut ctx.accounts.market;
         m.liquidity_usdc_fp = m
             .liquidity_usdc_fp
             .checked_add(usdc_amount_fp as i128)
             .ok_or(AmmError::MathOverflow)?;
+        m.lp_liquidity_fp = m
+            .lp_liquidity_fp
+            .checked_add(usdc_amount_fp as i128)
+            .ok_or(AmmError::MathOverflow)?;
+        apply_auto_b(m, ctx.accounts.vault_usdc.amount)?;
+        emit!(LiquidityAdded {
+            market: m.key(),
+            provider: ctx.accounts.provider.key(),
+            usdc_fp: usdc_amount_fp,
+            lp_minted: usdc_amount_fp,
+        });
+        Ok(())
+    }
+
+    /// After settlement, burn LP tokens for a pro-rata share of the LP residual pool.
+    pub fn remove_liquidity(ctx: Context<RemoveLiquidity>, lp_amount: u64) -> Result<()> {
+        require!(ctx.accounts.market.outcome != Outcome::Unresolved, AmmError::Unsettled);
+        require!(lp_amount > 0, AmmError::InvalidAmount);
+        let lp_supply = ctx.accounts.lp_mint.supply;
+        require!(lp_amount <= lp_supply, AmmError::InsufficientBalance);
+
+        let vault_amount = ctx.accounts.vault_usdc.amount;
+        ctx.accounts.market.snapshot_residual(vault_amount)?;
+        let payout_fp = ctx
+            .accounts
+            .market
+            .lp_pool_fp
+            .checked_mul(lp_amount as i128)
+            .ok_or(AmmError::MathOverflow)?
+            / (lp_supply as i128);
+
+        token::burn(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Burn {
+                    mint: ctx.accounts.lp_mint.to_account_info(),
+                    from: ctx.accounts.provider_lp.to_account_info(),
+                    authority: ctx.accounts.provider.to_account_info(),
+                },
+            ),
+            lp_amount,
+        )?;
+
+        let pda_authority = ctx.accounts.market.authority;
+        let bump = ctx.accounts.market.bump;
+        let milestone_id = ctx.accounts.market.milestone_id.clone();
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        if payout_fp > 0 {
+            vault_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault_usdc,
+                ctx.accounts.provider_usdc.to_account_info(),
+                ctx.accounts.market.to_account_info(),
+                &seeds,
+                payout_fp as u64,
+            )?;
+        }
+
+        let m = &mut ctx.accounts.market;
+        m.lp_pool_fp = m.lp_pool_fp.checked_sub(payout_fp).ok_or(AmmError::MathOverflow)?;
+        emit!(LiquidityRemoved {
+            market: m.key(),
+            provider: ctx.accounts.provider.key(),
+            lp_burned: lp_amount,
+            usdc_fp: payout_fp as u64,
+        });
+        Ok(())
+    }
+
+    /// Sponsor a market's depth from any wallet. With `raise_b`, b is raised by what this
+    /// deposit alone funds at the current price (see `b_raise_for_deposit`), rescaling q so
+    /// the price is unchanged; a deposit too small to raise it is rejected.
+    pub fn sponsor_liquidity(
+        ctx: Context<SponsorLiquidity>,
+        usdc_amount_fp: u64,
+        raise_b: bool,
+    ) -> Result<()> {
+        let clock = Clock::get()?;
+        require!(!ctx.accounts.market.paused, AmmError::Paused);
+        require!(ctx.accounts.market.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!ctx.accounts.market.early_resolution, AmmError::EarlyResolutionPending);
+        require!(clock.unix_timestamp < ctx.accounts.market.deadline_ts, AmmError::AfterDeadline);
+        require!(usdc_amount_fp > 0, AmmError::InvalidAmount);
+
+        token::transfer(
+            CpiContext::new(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
+                    from: ctx.accounts.sponsor_usdc.to_account_info(),
+                    to: ctx.accounts.vault_usdc.to_account_info(),
+                    authority: ctx.accounts.sponsor.to_account_info(),
+                },
+            ),
+            usdc_amount_fp,
+        )?;
+
+        let s = &mut ctx.accounts.sponsorship;
+        if s.sponsor == Pubkey::default() {
+            s.sponsor = ctx.accounts.sponsor.key();
+            s.market = ctx.accounts.market.key();
+            s.bump = ctx.bumps.sponsorship;
+        }
+        s.amount_fp = s
+            .amount_fp
+            .checked_add(usdc_amount_fp as i128)
+            .ok_or(AmmError::MathOverflow)?;
+
+        ctx.accounts.vault_usdc.reload()?;
+        let m = &mut ctx.accounts.market;
+        let amount = usdc_amount_fp as i128;
+        m.liquidity_usdc_fp = m.liquidity_usdc_fp.checked_add(amount).ok_or(AmmError::MathOverflow)?;
+        m.sponsor_liquidity_fp = m
+            .sponsor_liquidity_fp
+            .checked_add(amount)
+            .ok_or(AmmError::MathOverflow)?;
+        m.sponsor_unclaimed_fp = m
+            .sponsor_unclaimed_fp
+            .checked_add(amount)
+            .ok_or(AmmError::MathOverflow)?;
+        if raise_b {
+            require_manual_b(m, m.auto_b)?;
+            let new_b_fp = m
+                .b_fp
+                .checked_add(b_raise_for_deposit(m, amount)?)
+                .ok_or(AmmError::MathOverflow)?
+                .min(MAX_B_FP);
+            require!(new_b_fp > m.b_fp, AmmError::InvalidB);
+            rebase_b(m, new_b_fp)?;
+        }
+        apply_auto_b(m, ctx.accounts.vault_usdc.amount)?;
+        emit!(LiquiditySponsored {
+            market: m.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            usdc_fp: usdc_amount_fp,
+            total_sponsored_fp: ctx.accounts.sponsorship.amount_fp as u64,
+            b_fp: m.b_fp as i64,
+        });
+        Ok(())
+    }
+
+    /// After settlement, refund a sponsor its pro-rata share of the sponsor residual pool.
+    pub fn claim_sponsor_refund(ctx: Context<ClaimSponsorRefund>) -> Result<()> {
+        require!(ctx.accounts.market.outcome != Outcome::Unresolved, AmmError::Unsettled);
+        require!(!ctx.accounts.sponsorship.refunded, AmmError::AlreadyClaimed);
+
+        let vault_amount = ctx.accounts.vault_usdc.amount;
+        ctx.accounts.market.snapshot_residual(vault_amount)?;
+        let contributed_fp = ctx.accounts.sponsorship.amount_fp;
+        let m = &ctx.accounts.market;
+        let refund_fp = m
+            .sponsor_pool_fp
+            .checked_mul(contributed_fp)
+            .ok_or(AmmError::MathOverflow)?
+            .checked_div(m.sponsor_unclaimed_fp.max(1))
+            .ok_or(AmmError::MathOverflow)?;
+
+        let pda_authority = m.authority;
+        let bump = m.bump;
+        let milestone_id = m.milestone_id.clone();
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        if refund_fp > 0 {
+            vault_transfer(
+                &ctx.accounts.token_program,
+                &ctx.accounts.vault_usdc,
+                ctx.accounts.sponsor_usdc.to_account_info(),
+                ctx.accounts.market.to_account_info(),
+                &seeds,
+                refund_fp as u64,
+            )?;
+        }
+
+        ctx.accounts.sponsorship.refunded = true;
+        let m = &mut ctx.accounts.market;
+        m.sponsor_pool_fp = m.sponsor_pool_fp.checked_sub(refund_fp).ok_or(AmmError::MathOverflow)?;
+        m.sponsor_unclaimed_fp = m
+            .sponsor_unclaimed_fp
+            .checked_sub(contributed_fp)
+            .ok_or(AmmError::MathOverflow)?;
+        emit!(SponsorRefunded {
+            market: m.key(),
+            sponsor: ctx.accounts.sponsor.key(),
+            amount_fp: refund_fp as u64,
+        });
         Ok(())
     }
 
@@ -93,28 +490,45 @@ pub mod milestone_amm {
         usdc_in_fp: u64,
         min_shares_out_fp: u64,
     ) -> Result<()> {
-        // Read-only snapshot of market fields we'll need for checks/math
+        // Bring b in line with the schedule (price-continuous), then snapshot market fields
         let clock = Clock::get()?;
+        apply_b_schedule(
+            &mut ctx.accounts.market,
+            clock.unix_timestamp,
+            ctx.accounts.vault_usdc.amount,
+        )?;
         let market_key = ctx.accounts.market.key();
         let paused = ctx.accounts.market.paused;
         let outcome = ctx.accounts.market.outcome;
         let deadline_ts = ctx.accounts.market.deadline_ts;
         let max_trade_usdc_fp = ctx.accounts.market.max_trade_usdc_fp;
         let max_pos_fp = ctx.accounts.market.max_position_shares_fp;
+        let min_interval = ctx.accounts.market.min_trade_interval_secs;
+        let max_trades = ctx.accounts.market.max_trades_per_window;
+        let window_secs = ctx.accounts.market.trade_window_secs;
         let usdc_mint = ctx.accounts.market.usdc_mint;
         let vault_usdc_pk = ctx.accounts.market.vault_usdc;
         let b_fp = ctx.accounts.market.b_fp;
-        let fee_bps = ctx.accounts.market.fee_bps;
+        let (fee_tier, discount_bps) = volume_fee_tier(
+            ctx.accounts.config.as_deref(),
+            ctx.accounts.trader_volume.as_deref(),
+            clock.unix_timestamp,
+        );
+        let fee_bps = trade_fee_bps(&ctx.accounts.market, clock.unix_timestamp, discount_bps)?;
         let q_hit0 = ctx.accounts.market.q_hit_fp;
         let q_miss0 = ctx.accounts.market.q_miss_fp;
         let pda_authority = ctx.accounts.market.authority;
         let bump = ctx.accounts.market.bump;
         let milestone_id = ctx.accounts.market.milestone_id.clone();
         let treasury_opt = ctx.accounts.market.treasury;
+        let referral_bps = referral_fee_bps(&ctx.accounts.market, ctx.accounts.config.as_deref());
+        let fee_split = ctx.accounts.market.fee_split;
+        let early_resolution = ctx.accounts.market.early_resolution;
 
         // Checks using the snapshot
         require!(!paused, AmmError::Paused);
         require!(outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!early_resolution, AmmError::EarlyResolutionPending);
         require!(clock.unix_timestamp < deadline_ts, AmmError::AfterDeadline);
         require!((usdc_in_fp as i128) <= max_trade_usdc_fp, AmmError::TradeTooLarge);
         require!(ctx.accounts.user_usdc.owner == ctx.accounts.user.key(), AmmError::InvalidOwner);
@@ -143,10 +557,12 @@ pub mod milestone_amm {
             pos.market = market_key;
             pos.hit_shares_fp = 0;
             pos.miss_shares_fp = 0;
+            pos.net_cost_fp = 0;
         } else {
             require!(pos.owner == ctx.accounts.user.key(), AmmError::Unauthorized);
             require!(pos.market == market_key, AmmError::WrongMarket);
         }
+        record_trade(pos, clock.unix_timestamp, min_interval, max_trades, window_secs)?;
 
         // Net spendable estimate after fee
         let fee_mul = 10_000u64
@@ -172,54 +588,83 @@ pub mod milestone_amm {
 
         // Cost and fee on snapshot curve
         let dcost_fp = lmsr_delta_cost(b_fp, q_hit0, q_miss0, side, delta_q)?;
-        let fee_fp = (dcost_fp as i128)
-            .checked_mul(fee_bps as i128)
-            .ok_or(AmmError::MathOverflow)?
-            / 10_000i128;
+        let fee_fp = fee_from_bps(dcost_fp, fee_bps)?;
         let total_due_fp = (dcost_fp as i128)
             .checked_add(fee_fp)
             .ok_or(AmmError::MathOverflow)?;
         require!((usdc_in_fp as i128) >= total_due_fp, AmmError::InsufficientPayment);
 
         // Route fee (if any) using a short immutable borrow of market AccountInfo (no &mut held)
-        if treasury_opt.is_some() && fee_fp > 0 {
-            let seeds = [
-                SEED_MARKET,
-                pda_authority.as_ref(),
-                milestone_id.as_ref(),
-                &[bump],
-            ];
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.vault_usdc.to_account_info(),
-                        to: ctx.accounts.treasury_usdc.to_account_info(),
-                        authority: ctx.accounts.market.to_account_info(),
-                    },
-                    &[&seeds],
-                ),
-                fee_fp as u64,
-            )?;
-        }
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        let routed = route_trade_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_usdc,
+            ctx.accounts.market.to_account_info(),
+            ctx.accounts.treasury_usdc.as_ref(),
+            ctx.accounts.referrer_usdc.as_ref(),
+            &seeds,
+            fee_fp,
+            referral_bps,
+            fee_split,
+            treasury_opt,
+            usdc_mint,
+        )?;
 
         // Now take a fresh mutable borrow to update market + position
         {
             let m = &mut ctx.accounts.market;
+            m.accrue_fees(&routed)?;
+            if let Some(vol) = ctx.accounts.trader_volume.as_mut() {
+                if m.volume_tracked && m.fee_bps > 0 {
+                    vol.record(clock.unix_timestamp, dcost_fp as u64);
+                }
+            }
             match side {
                 Side::Hit => {
                     m.q_hit_fp = m.q_hit_fp.checked_add(delta_q).ok_or(AmmError::MathOverflow)?;
+                    m.hit_shares_outstanding_fp = m
+                        .hit_shares_outstanding_fp
+                        .checked_add(delta_q)
+                        .ok_or(AmmError::MathOverflow)?;
                     pos.hit_shares_fp =
                         pos.hit_shares_fp.checked_add(delta_q).ok_or(AmmError::MathOverflow)?;
                     require!(pos.hit_shares_fp <= m.max_position_shares_fp, AmmError::PositionTooLarge);
                 }
                 Side::Miss => {
                     m.q_miss_fp = m.q_miss_fp.checked_add(delta_q).ok_or(AmmError::MathOverflow)?;
+                    m.miss_shares_outstanding_fp = m
+                        .miss_shares_outstanding_fp
+                        .checked_add(delta_q)
+                        .ok_or(AmmError::MathOverflow)?;
                     pos.miss_shares_fp =
                         pos.miss_shares_fp.checked_add(delta_q).ok_or(AmmError::MathOverflow)?;
                     require!(pos.miss_shares_fp <= m.max_position_shares_fp, AmmError::PositionTooLarge);
                 }
             }
+            add_cost_basis(m, pos, dcost_fp as i128)?;
+
+            // Market-wide caps (0 = uncapped)
+            if m.max_side_shares_fp > 0 {
+                require!(
+                    m.hit_shares_outstanding_fp <= m.max_side_shares_fp
+                        && m.miss_shares_outstanding_fp <= m.max_side_shares_fp,
+                    AmmError::OpenInterestTooLarge
+                );
+            }
+            if m.max_imbalance_fp > 0 {
+                // A buy may always shrink an imbalance that is already over the cap
+                let before = q_hit0.checked_sub(q_miss0).ok_or(AmmError::MathOverflow)?;
+                let imbalance = m.q_hit_fp.checked_sub(m.q_miss_fp).ok_or(AmmError::MathOverflow)?;
+                require!(
+                    imbalance.abs() <= m.max_imbalance_fp.max(before.abs()),
+                    AmmError::ImbalanceTooLarge
+                );
+            }
             let p_hit = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
             emit!(TradeEvent {
                 market: m.key(),
@@ -229,6 +674,9 @@ pub mod milestone_amm {
                 usdc_fp: dcost_fp as u64,
                 shares_fp: delta_q as u64,
                 fee_fp: fee_fp as u64,
+                referrer: routed.referrer,
+                referral_fee_fp: routed.referral_fp as u64,
+                fee_tier,
                 p_hit_milli: (p_hit * (PRICE_MILLI_SCALER as f64)) as i64,
             });
         }
@@ -242,25 +690,44 @@ pub mod milestone_amm {
         shares_in_fp: u64,
         min_usdc_out_fp: u64,
     ) -> Result<()> {
-        // Snapshot reads
+        // Snapshot reads (after syncing b to the schedule)
         let clock = Clock::get()?;
+        apply_b_schedule(
+            &mut ctx.accounts.market,
+            clock.unix_timestamp,
+            ctx.accounts.vault_usdc.amount,
+        )?;
         let paused = ctx.accounts.market.paused;
         let outcome = ctx.accounts.market.outcome;
         let deadline_ts = ctx.accounts.market.deadline_ts;
         let usdc_mint = ctx.accounts.market.usdc_mint;
         let vault_usdc_pk = ctx.accounts.market.vault_usdc;
+        let max_sell_shares_fp = ctx.accounts.market.max_sell_shares_fp;
+        let max_sell_usdc_fp = ctx.accounts.market.max_sell_usdc_fp;
+        let min_interval = ctx.accounts.market.min_trade_interval_secs;
+        let max_trades = ctx.accounts.market.max_trades_per_window;
+        let window_secs = ctx.accounts.market.trade_window_secs;
         let b_fp = ctx.accounts.market.b_fp;
-        let fee_bps = ctx.accounts.market.fee_bps;
+        let (fee_tier, discount_bps) = volume_fee_tier(
+            ctx.accounts.config.as_deref(),
+            ctx.accounts.trader_volume.as_deref(),
+            clock.unix_timestamp,
+        );
+        let fee_bps = trade_fee_bps(&ctx.accounts.market, clock.unix_timestamp, discount_bps)?;
         let q_hit0 = ctx.accounts.market.q_hit_fp;
         let q_miss0 = ctx.accounts.market.q_miss_fp;
         let pda_authority = ctx.accounts.market.authority;
         let bump = ctx.accounts.market.bump;
         let milestone_id = ctx.accounts.market.milestone_id.clone();
         let treasury_opt = ctx.accounts.market.treasury;
+        let referral_bps = referral_fee_bps(&ctx.accounts.market, ctx.accounts.config.as_deref());
+        let fee_split = ctx.accounts.market.fee_split;
+        let early_resolution = ctx.accounts.market.early_resolution;
 
         // Checks
         require!(!paused, AmmError::Paused);
         require!(outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!early_resolution, AmmError::EarlyResolutionPending);
         require!(clock.unix_timestamp < deadline_ts, AmmError::AfterDeadline);
         require!(ctx.accounts.user_usdc.owner == ctx.accounts.user.key(), AmmError::InvalidOwner);
         require!(ctx.accounts.user_usdc.mint == usdc_mint, AmmError::WrongMint);
@@ -269,25 +736,27 @@ pub mod milestone_amm {
         let pos = &mut ctx.accounts.position;
         require!(pos.owner == ctx.accounts.user.key(), AmmError::Unauthorized);
         require!(pos.market == ctx.accounts.market.key(), AmmError::WrongMarket);
+        record_trade(pos, clock.unix_timestamp, min_interval, max_trades, window_secs)?;
 
         let delta_q = shares_in_fp as i128;
         require!(delta_q > 0, AmmError::InvalidAmount);
+        require!(delta_q <= max_sell_shares_fp, AmmError::SellTooLarge);
 
         match side {
             Side::Hit => require!(pos.hit_shares_fp >= delta_q, AmmError::InsufficientBalance),
             Side::Miss => require!(pos.miss_shares_fp >= delta_q, AmmError::InsufficientBalance),
         }
 
-        // ΔC for decreasing quantity (negative delta inside helper)
-        let dcost_fp = lmsr_delta_cost(b_fp, q_hit0, q_miss0, side, -delta_q)?;
-        let fee_fp = (dcost_fp as i128)
-            .checked_mul(fee_bps as i128)
-            .ok_or(AmmError::MathOverflow)?
-            / 10_000i128;
+        // ΔC for decreasing quantity (negative delta inside helper); proceeds are -ΔC
+        let dcost_fp = lmsr_delta_cost(b_fp, q_hit0, q_miss0, side, -delta_q)?
+            .checked_neg()
+            .ok_or(AmmError::MathOverflow)?;
+        let fee_fp = fee_from_bps(dcost_fp, fee_bps)?;
         let payout_fp = (dcost_fp as i128)
             .checked_sub(fee_fp)
             .ok_or(AmmError::MathOverflow)?;
         require!(payout_fp >= (min_usdc_out_fp as i128), AmmError::Slippage);
+        require!(payout_fp <= max_sell_usdc_fp, AmmError::SellTooLarge);
 
         // Pay user from vault (market PDA is authority) — in its own scope (no &mut market held)
         if payout_fp > 0 {
@@ -311,43 +780,57 @@ pub mod milestone_amm {
             )?;
         }
 
-        // Fee to treasury if any
-        if treasury_opt.is_some() && fee_fp > 0 {
-            let seeds = [
-                SEED_MARKET,
-                pda_authority.as_ref(),
-                milestone_id.as_ref(),
-                &[bump],
-            ];
-            token::transfer(
-                CpiContext::new_with_signer(
-                    ctx.accounts.token_program.to_account_info(),
-                    Transfer {
-                        from: ctx.accounts.vault_usdc.to_account_info(),
-                        to: ctx.accounts.treasury_usdc.to_account_info(),
-                        authority: ctx.accounts.market.to_account_info(),
-                    },
-                    &[&seeds],
-                ),
-                fee_fp as u64,
-            )?;
-        }
+        // Fee to referrer / treasury if any
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        let routed = route_trade_fee(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_usdc,
+            ctx.accounts.market.to_account_info(),
+            ctx.accounts.treasury_usdc.as_ref(),
+            ctx.accounts.referrer_usdc.as_ref(),
+            &seeds,
+            fee_fp,
+            referral_bps,
+            fee_split,
+            treasury_opt,
+            usdc_mint,
+        )?;
 
         // Now mutate market and position
         {
             let m = &mut ctx.accounts.market;
+            m.accrue_fees(&routed)?;
+            if let Some(vol) = ctx.accounts.trader_volume.as_mut() {
+                if m.volume_tracked && m.fee_bps > 0 {
+                    vol.record(clock.unix_timestamp, dcost_fp as u64);
+                }
+            }
             match side {
                 Side::Hit => {
                     m.q_hit_fp = m.q_hit_fp.checked_sub(delta_q).ok_or(AmmError::MathOverflow)?;
+                    m.hit_shares_outstanding_fp = m
+                        .hit_shares_outstanding_fp
+                        .checked_sub(delta_q)
+                        .ok_or(AmmError::MathOverflow)?;
                     pos.hit_shares_fp =
                         pos.hit_shares_fp.checked_sub(delta_q).ok_or(AmmError::MathOverflow)?;
                 }
                 Side::Miss => {
                     m.q_miss_fp = m.q_miss_fp.checked_sub(delta_q).ok_or(AmmError::MathOverflow)?;
+                    m.miss_shares_outstanding_fp = m
+                        .miss_shares_outstanding_fp
+                        .checked_sub(delta_q)
+                        .ok_or(AmmError::MathOverflow)?;
                     pos.miss_shares_fp =
                         pos.miss_shares_fp.checked_sub(delta_q).ok_or(AmmError::MathOverflow)?;
                 }
             }
+            add_cost_basis(m, pos, -(dcost_fp as i128))?;
             let p_hit = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
             emit!(TradeEvent {
                 market: m.key(),
@@ -357,14 +840,29 @@ pub mod milestone_amm {
                 usdc_fp: dcost_fp as u64,
                 shares_fp: shares_in_fp,
                 fee_fp: fee_fp as u64,
+                referrer: routed.referrer,
+                referral_fee_fp: routed.referral_fp as u64,
+                fee_tier,
                 p_hit_milli: (p_hit * (PRICE_MILLI_SCALER as f64)) as i64,
             });
         }
         Ok(())
     }
 
-    /// Settle the market to Hit or Miss once the window passes.
-    pub fn settle_market(ctx: Context<Settle>, outcome: Outcome) -> Result<()> {
+    /// Settle the market once the window passes. `Partial` requires `hit_payout_fp`, the
+    /// fixed-point fraction each HIT share redeems for (MISS gets one minus it).
+    /// Instead of the oracle signing, anyone may relay an `attestation`: an ed25519
+    /// signature by `oracle_signer` verified by the preceding Ed25519 program instruction.
+    /// `evidence_uri` and `evidence_hash` (of the supporting documents) are kept in the
+    /// `SettlementRecord` PDA.
+    pub fn settle_market(
+        ctx: Context<Settle>,
+        outcome: Outcome,
+        hit_payout_fp: Option<u64>,
+        attestation: Option<OracleAttestation>,
+        evidence_uri: String,
+        evidence_hash: [u8; 32],
+    ) -> Result<()> {
         let clock = Clock::get()?;
         let deadline_ts = ctx.accounts.market.deadline_ts;
         let grace = ctx.accounts.market.grace_period_secs;
@@ -382,61 +880,460 @@ pub mod milestone_amm {
                 authorized = ctx.accounts.oracle_signer.is_some()
                     && ctx.accounts.oracle_signer.as_ref().unwrap().key.eq(or);
             }
+            if let (false, Some(att)) = (authorized, attestation) {
+                require!(
+                    att.nonce > ctx.accounts.market.attestation_nonce,
+                    AmmError::InvalidAttestation
+                );
+                require!(
+                    att.timestamp >= deadline_ts + grace && att.timestamp <= clock.unix_timestamp,
+                    AmmError::InvalidAttestation
+                );
+                let ix_ai = ctx.accounts.instructions.as_ref().ok_or(AmmError::InvalidAttestation)?;
+                let message = attestation_message(
+                    &ctx.accounts.market.key(),
+                    outcome,
+                    hit_payout_fp.unwrap_or(0),
+                    &att,
+                    &evidence_uri,
+                    &evidence_hash,
+                );
+                verify_ed25519_attestation(ix_ai, or, &message)?;
+                authorized = true;
+                ctx.accounts.market.attestation_nonce = att.nonce;
+            }
         }
         require!(authorized, AmmError::Unauthorized);
-        require!(matches!(outcome, Outcome::Hit | Outcome::Miss), AmmError::InvalidOutcome);
+        let settler = match &ctx.accounts.authority {
+            Some(a) if a.key() == ctx.accounts.market.authority => a.key(),
+            _ => ctx.accounts.market.oracle_signer.ok_or(AmmError::Unauthorized)?,
+        };
+        require!(ctx.accounts.market.oracle_threshold == 0, AmmError::QuorumRequired);
+        require!(!ctx.accounts.market.early_resolution, AmmError::EarlyResolutionPending);
+        require_escalated(&ctx.accounts.market, true)?;
+        require!(evidence_uri.len() <= MAX_EVIDENCE_URI_LEN, AmmError::EvidenceUriTooLong);
+        require!(outcome != Outcome::Unresolved, AmmError::InvalidOutcome);
+        if outcome == Outcome::Partial {
+            let fraction = hit_payout_fp.ok_or(AmmError::InvalidPayoutFraction)? as i128;
+            require!(fraction > 0 && fraction < FP_SCALER, AmmError::InvalidPayoutFraction);
+            ctx.accounts.market.hit_payout_fp = fraction;
+        } else {
+            require!(hit_payout_fp.is_none(), AmmError::InvalidPayoutFraction);
+        }
 
-        let m = &mut ctx.accounts.market;
-        m.outcome = outcome;
-        m.paused = true;
+        let rec = &mut ctx.accounts.settlement_record;
+        rec.market = ctx.accounts.market.key();
+        rec.outcome = outcome;
+        rec.hit_payout_fp = hit_payout_fp.unwrap_or(0);
+        rec.settler = settler;
+        rec.settled_at = clock.unix_timestamp;
+        rec.evidence_uri = evidence_uri.clone();
+        rec.evidence_hash = evidence_hash;
+        rec.bump = ctx.bumps.settlement_record;
 
-        emit!(Settled { market: m.key(), outcome });
-        Ok(())
+        apply_settlement_with_evidence(&mut ctx.accounts.market, outcome, evidence_uri, evidence_hash)
     }
 
-    /// Redeem winning shares for USDC @ 1.0 per share after settlement.
-    pub fn redeem(ctx: Context<Redeem>) -> Result<()> {
-        require!(ctx.accounts.market.outcome != Outcome::Unresolved, AmmError::Unsettled);
-        require!(ctx.accounts.position.owner == ctx.accounts.user.key(), AmmError::Unauthorized);
-        require!(ctx.accounts.position.market == ctx.accounts.market.key(), AmmError::WrongMarket);
+    /// Record one quorum oracle's vote; settles once `oracle_threshold` votes agree. A quorum
+    /// reached before the settlement window opens starts an early resolution instead.
+    pub fn submit_oracle_vote(ctx: Context<SubmitOracleVote>, outcome: Outcome) -> Result<()> {
+        let clock = Clock::get()?;
+        let oracle = ctx.accounts.oracle.key();
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(m.oracle_threshold > 0, AmmError::QuorumNotConfigured);
+        require!(!m.early_resolution, AmmError::EarlyResolutionPending);
+        let window_open = clock.unix_timestamp >= m.deadline_ts + m.grace_period_secs;
+        require_escalated(m, window_open)?;
+        require!(m.oracles.contains(&oracle), AmmError::Unauthorized);
+        require!(matches!(outcome, Outcome::Hit | Outcome::Miss | Outcome::Void), AmmError::InvalidOutcome);
 
-        // Snapshot fields needed for CPI
-        let outcome = ctx.accounts.market.outcome;
-        let pda_authority = ctx.accounts.market.authority;
-        let bump = ctx.accounts.market.bump;
-        let milestone_id = ctx.accounts.market.milestone_id.clone();
+        let market_key = m.key();
+        let threshold = m.oracle_threshold as usize;
+        let v = &mut ctx.accounts.votes;
+        if v.market == Pubkey::default() {
+            v.market = market_key;
+            v.bump = ctx.bumps.votes;
+        }
+        // Only votes from the current oracle set count; drop any left by a replaced set
+        v.votes.retain(|x| ctx.accounts.market.oracles.contains(&x.oracle));
+        require!(v.votes.iter().all(|x| x.oracle != oracle), AmmError::AlreadyVoted);
+        require!(v.votes.len() < MAX_ORACLES, AmmError::InvalidOracleSet);
 
-        // Compute redemption amounts and zero out shares with a mutable borrow of position only
-        let redeem_fp = match outcome {
-            Outcome::Hit => {
-                let amt = ctx.accounts.position.hit_shares_fp;
-                ctx.accounts.position.hit_shares_fp = 0;
-                ctx.accounts.position.miss_shares_fp = 0;
-                amt
-            }
-            Outcome::Miss => {
-                let amt = ctx.accounts.position.miss_shares_fp;
-                ctx.accounts.position.hit_shares_fp = 0;
-                ctx.accounts.position.miss_shares_fp = 0;
-                amt
+        if let Some(other) = v.votes.iter().find(|x| x.outcome != outcome).copied() {
+            v.conflicted = true;
+            emit!(OracleVoteConflict {
+                market: market_key,
+                oracle,
+                outcome,
+                conflicting_oracle: other.oracle,
+                conflicting_outcome: other.outcome,
+            });
+        }
+        v.votes.push(OracleVote { oracle, outcome });
+        let matching = v.votes.iter().filter(|x| x.outcome == outcome).count();
+        emit!(OracleVoted {
+            market: market_key,
+            oracle,
+            outcome,
+            matching_votes: matching as u8,
+            threshold: threshold as u8,
+        });
+
+        if matching >= threshold {
+            if window_open {
+                apply_settlement(&mut ctx.accounts.market, outcome)?;
+            } else {
+                begin_early_resolution(&mut ctx.accounts.market, outcome, clock.unix_timestamp);
             }
-            Outcome::Unresolved => unreachable!(),
-        };
-        if redeem_fp <= 0 {
-            return Ok(());
         }
+        Ok(())
+    }
 
-        // Pay from vault to user (no &mut market held while doing CPI)
-        let seeds = [
-            SEED_MARKET,
-            pda_authority.as_ref(),
-            milestone_id.as_ref(),
-            &[bump],
-        ];
-        token::transfer(
-            CpiContext::new_with_signer(
-                ctx.accounts.token_program.to_account_info(),
-                Transfer {
+    /// Authority/oracle: resolve before the settlement window opens. Trading halts at once;
+    /// the outcome is final after `EARLY_RESOLUTION_DELAY_SECS` via `finalize_early_resolution`.
+    pub fn early_resolve(ctx: Context<EarlyResolve>, outcome: Outcome) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!m.early_resolution, AmmError::EarlyResolutionPending);
+        require!(
+            clock.unix_timestamp < m.deadline_ts + m.grace_period_secs,
+            AmmError::SettlementWindowOpen
+        );
+        let authorized = ctx.accounts.authority.as_ref().is_some_and(|a| a.key() == m.authority)
+            || m.oracle_signer.is_some_and(|o| {
+                ctx.accounts.oracle_signer.as_ref().is_some_and(|s| s.key() == o)
+            });
+        require!(authorized, AmmError::Unauthorized);
+        require!(m.oracle_threshold == 0, AmmError::QuorumRequired);
+        require_escalated(m, false)?;
+        require!(matches!(outcome, Outcome::Hit | Outcome::Miss | Outcome::Void), AmmError::InvalidOutcome);
+
+        begin_early_resolution(&mut ctx.accounts.market, outcome, clock.unix_timestamp);
+        Ok(())
+    }
+
+    /// Permissionless: settle a pending early resolution once its delay has passed.
+    pub fn finalize_early_resolution(ctx: Context<FinalizeEarlyResolution>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(m.early_resolution, AmmError::NoEarlyResolution);
+        require!(
+            clock.unix_timestamp >= m.early_resolution_ts + EARLY_RESOLUTION_DELAY_SECS,
+            AmmError::EarlyResolutionPending
+        );
+        let outcome = m.early_outcome;
+        apply_settlement(&mut ctx.accounts.market, outcome)
+    }
+
+    /// Authority: withdraw a pending early resolution while its delay is still running, and
+    /// resume trading. With a quorum the votes that reached it are cleared too.
+    pub fn cancel_early_resolution(ctx: Context<CancelEarlyResolution>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(ctx.accounts.authority.key() == m.authority, AmmError::Unauthorized);
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(m.early_resolution, AmmError::NoEarlyResolution);
+        require!(
+            clock.unix_timestamp < m.early_resolution_ts + EARLY_RESOLUTION_DELAY_SECS,
+            AmmError::EarlyResolutionFinal
+        );
+        if m.oracle_threshold > 0 {
+            let v = ctx.accounts.votes.as_mut().ok_or(AmmError::VotesAccountMissing)?;
+            v.votes.clear();
+            v.conflicted = false;
+        }
+
+        let m = &mut ctx.accounts.market;
+        let outcome = m.early_outcome;
+        m.early_resolution = false;
+        m.early_resolution_ts = 0;
+        m.early_outcome = Outcome::Unresolved;
+        emit!(EarlyResolutionCancelled {
+            market: m.key(),
+            outcome,
+        });
+        Ok(())
+    }
+
+    /// Optimistic settlement: propose an outcome by posting `settlement_bond_fp`.
+    pub fn propose_outcome(ctx: Context<PostBond>, outcome: Outcome) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(m.settlement_bond_fp > 0, AmmError::OptimisticNotEnabled);
+        require!(m.proposal.is_none(), AmmError::ProposalExists);
+        require!(!m.early_resolution, AmmError::EarlyResolutionPending);
+        require!(
+            clock.unix_timestamp >= m.deadline_ts + m.grace_period_secs,
+            AmmError::BeforeSettlementWindow
+        );
+        require!(matches!(outcome, Outcome::Hit | Outcome::Miss | Outcome::Void), AmmError::InvalidOutcome);
+        let bond_fp = m.settlement_bond_fp;
+
+        ctx.accounts.post_bond(bond_fp as u64)?;
+        let m = &mut ctx.accounts.market;
+        m.proposal = Some(SettlementProposal {
+            proposer: ctx.accounts.bonder.key(),
+            outcome,
+            proposed_at: clock.unix_timestamp,
+            bond_fp,
+            disputer: None,
+            bonds_claimed: false,
+            disputer_bond_claimed: false,
+        });
+        emit!(OutcomeProposed {
+            market: m.key(),
+            proposer: ctx.accounts.bonder.key(),
+            outcome,
+            bond_fp: bond_fp as u64,
+            challenge_ends_ts: clock.unix_timestamp + m.challenge_window_secs,
+        });
+        Ok(())
+    }
+
+    /// Dispute the pending proposal within the challenge window by matching its bond.
+    /// Resolution escalates to the authority/oracle (`settle_market`) or the oracle quorum.
+    pub fn dispute_outcome(ctx: Context<PostBond>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        let p = m.proposal.ok_or(AmmError::NoProposal)?;
+        require!(p.disputer.is_none(), AmmError::AlreadyDisputed);
+        require!(ctx.accounts.bonder.key() != p.proposer, AmmError::Unauthorized);
+        require!(
+            clock.unix_timestamp < p.proposed_at + m.challenge_window_secs,
+            AmmError::ChallengeWindowClosed
+        );
+
+        ctx.accounts.post_bond(p.bond_fp as u64)?;
+        let m = &mut ctx.accounts.market;
+        if let Some(p) = m.proposal.as_mut() {
+            p.disputer = Some(ctx.accounts.bonder.key());
+        }
+        emit!(OutcomeDisputed {
+            market: m.key(),
+            disputer: ctx.accounts.bonder.key(),
+            proposed_outcome: p.outcome,
+        });
+        Ok(())
+    }
+
+    /// Permissionless: settle to the proposed outcome once the window passes undisputed.
+    pub fn finalize_proposal(ctx: Context<FinalizeProposal>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        let p = m.proposal.ok_or(AmmError::NoProposal)?;
+        require!(p.disputer.is_none(), AmmError::AlreadyDisputed);
+        require!(
+            clock.unix_timestamp >= p.proposed_at + m.challenge_window_secs,
+            AmmError::ChallengeWindowOpen
+        );
+        apply_settlement(&mut ctx.accounts.market, p.outcome)
+    }
+
+    /// Permissionless fallback: void a market nobody settled within `settlement_timeout_secs`
+    /// of the settlement window opening, so positions can redeem refunds. Not while a settlement
+    /// is already under way that needs no resolver: a pending early resolution or an undisputed
+    /// proposal (which anyone can finalize once its window passes).
+    pub fn force_void(ctx: Context<ForceVoid>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!m.early_resolution, AmmError::EarlyResolutionPending);
+        require!(
+            !matches!(m.proposal, Some(p) if p.disputer.is_none()),
+            AmmError::ProposalExists
+        );
+        let void_after = m
+            .deadline_ts
+            .checked_add(m.grace_period_secs)
+            .and_then(|t| t.checked_add(m.settlement_timeout_secs))
+            .ok_or(AmmError::MathOverflow)?;
+        require!(clock.unix_timestamp >= void_after, AmmError::SettlementTimeoutPending);
+        ctx.accounts.market.force_voided = true;
+        apply_settlement(&mut ctx.accounts.market, Outcome::Void)
+    }
+
+    /// Permissionless: once the settlement window opens, settle to Hit if the configured
+    /// feed's price satisfies the resolver's comparison with its threshold, else Miss.
+    /// The price must be trading, published within `max_staleness_secs` after the deadline,
+    /// and have a confidence interval within `max_conf_bps` of price. Not while an optimistic
+    /// proposal is open.
+    pub fn resolve_from_feed(ctx: Context<ResolveFromFeed>) -> Result<()> {
+        let clock = Clock::get()?;
+        let m = &ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(!m.early_resolution, AmmError::EarlyResolutionPending);
+        require!(m.proposal.is_none(), AmmError::ProposalExists);
+        require!(
+            clock.unix_timestamp >= m.deadline_ts + m.grace_period_secs,
+            AmmError::BeforeSettlementWindow
+        );
+        let resolver = m.feed_resolver.ok_or(AmmError::FeedNotConfigured)?;
+        require!(ctx.accounts.feed.key() == resolver.feed, AmmError::InvalidFeed);
+
+        let feed = read_pyth_price(&ctx.accounts.feed.try_borrow_data()?)?;
+        let outcome = feed_outcome(&resolver, &feed, m.deadline_ts)?;
+        emit!(FeedResolved {
+            market: m.key(),
+            feed: resolver.feed,
+            price: feed.price,
+            conf: feed.conf,
+            expo: feed.expo,
+            publish_ts: feed.publish_ts,
+            outcome,
+        });
+        apply_settlement(&mut ctx.accounts.market, outcome)
+    }
+
+    /// After settlement, the winning side of the proposal takes the escrowed bonds: the
+    /// proposer if the final outcome matches the proposal, otherwise the disputer. A dispute
+    /// left unresolved until `force_void` refunds each bond to its poster.
+    pub fn claim_bond(ctx: Context<ClaimBond>) -> Result<()> {
+        let m = &ctx.accounts.market;
+        require!(m.outcome != Outcome::Unresolved, AmmError::Unsettled);
+        let p = m.proposal.ok_or(AmmError::NoProposal)?;
+        let claimant = ctx.accounts.claimant.key();
+        // A force-voided dispute was never adjudicated, and a Void or Partial ruling against the
+        // proposal proves neither side right (a dispute only says "not this outcome"): each side
+        // gets its own bond back
+        let refund_only = p.disputer.is_some()
+            && (m.force_voided
+                || (m.outcome != p.outcome && matches!(m.outcome, Outcome::Void | Outcome::Partial)));
+        let (winner, amount_fp) = if refund_only {
+            if claimant == p.proposer {
+                require!(!p.bonds_claimed, AmmError::AlreadyClaimed);
+            } else {
+                require!(p.disputer == Some(claimant), AmmError::Unauthorized);
+                require!(!p.disputer_bond_claimed, AmmError::AlreadyClaimed);
+            }
+            (claimant, p.bond_fp)
+        } else {
+            require!(!p.bonds_claimed, AmmError::AlreadyClaimed);
+            match p.disputer {
+                None => (p.proposer, p.bond_fp),
+                Some(_) if m.outcome == p.outcome => (p.proposer, p.bond_fp * 2),
+                Some(d) => (d, p.bond_fp * 2),
+            }
+        };
+        require!(claimant == winner, AmmError::Unauthorized);
+
+        let seeds = [
+            SEED_MARKET,
+            m.authority.as_ref(),
+            m.milestone_id.as_ref(),
+            core::slice::from_ref(&m.bump),
+        ];
+        vault_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_usdc,
+            ctx.accounts.claimant_usdc.to_account_info(),
+            m.to_account_info(),
+            &seeds,
+            amount_fp as u64,
+        )?;
+
+        let m = &mut ctx.accounts.market;
+        m.bond_escrow_fp = m.bond_escrow_fp.checked_sub(amount_fp).ok_or(AmmError::MathOverflow)?;
+        if let Some(p) = m.proposal.as_mut() {
+            if !refund_only || winner == p.proposer {
+                p.bonds_claimed = true;
+            }
+            if !refund_only || Some(winner) == p.disputer {
+                p.disputer_bond_claimed = true;
+            }
+        }
+        emit!(BondClaimed {
+            market: m.key(),
+            claimant: winner,
+            amount_fp: amount_fp as u64,
+        });
+        Ok(())
+    }
+
+    /// Redeem winning shares for USDC @ 1.0 per share after settlement.
+    pub fn redeem(ctx: Context<Redeem>) -> Result<()> {
+        require!(ctx.accounts.market.outcome != Outcome::Unresolved, AmmError::Unsettled);
+        require!(ctx.accounts.position.owner == ctx.accounts.user.key(), AmmError::Unauthorized);
+        require!(ctx.accounts.position.market == ctx.accounts.market.key(), AmmError::WrongMarket);
+
+        // Fix the residual (and any Void haircut) before this position leaves the totals
+        let vault_amount = ctx.accounts.vault_usdc.amount;
+        ctx.accounts.market.snapshot_residual(vault_amount)?;
+
+        // Snapshot fields needed for CPI
+        let outcome = ctx.accounts.market.outcome;
+        let pda_authority = ctx.accounts.market.authority;
+        let bump = ctx.accounts.market.bump;
+        let milestone_id = ctx.accounts.market.milestone_id.clone();
+
+        // Retire both sides of the position (and its cost basis) from the outstanding totals
+        let hit_fp = ctx.accounts.position.hit_shares_fp;
+        let miss_fp = ctx.accounts.position.miss_shares_fp;
+        let cost_basis_fp = ctx.accounts.position.net_cost_fp.max(0);
+        let void_fp = void_refund_fp(ctx.accounts.market.void_refund, hit_fp, miss_fp, cost_basis_fp)?;
+        {
+            let m = &mut ctx.accounts.market;
+            m.cost_basis_outstanding_fp = m
+                .cost_basis_outstanding_fp
+                .checked_sub(cost_basis_fp)
+                .ok_or(AmmError::MathOverflow)?;
+            m.hit_shares_outstanding_fp = m
+                .hit_shares_outstanding_fp
+                .checked_sub(hit_fp)
+                .ok_or(AmmError::MathOverflow)?;
+            m.miss_shares_outstanding_fp = m
+                .miss_shares_outstanding_fp
+                .checked_sub(miss_fp)
+                .ok_or(AmmError::MathOverflow)?;
+        }
+
+        // Compute redemption amounts and zero out shares with a mutable borrow of position only
+        ctx.accounts.position.net_cost_fp = 0;
+        let redeem_fp = match outcome {
+            Outcome::Hit => {
+                let amt = ctx.accounts.position.hit_shares_fp;
+                ctx.accounts.position.hit_shares_fp = 0;
+                ctx.accounts.position.miss_shares_fp = 0;
+                amt
+            }
+            Outcome::Miss => {
+                let amt = ctx.accounts.position.miss_shares_fp;
+                ctx.accounts.position.hit_shares_fp = 0;
+                ctx.accounts.position.miss_shares_fp = 0;
+                amt
+            }
+            Outcome::Partial => {
+                ctx.accounts.position.hit_shares_fp = 0;
+                ctx.accounts.position.miss_shares_fp = 0;
+                split_payout_fp(hit_fp, miss_fp, ctx.accounts.market.hit_payout_fp)?
+            }
+            Outcome::Void => {
+                ctx.accounts.position.hit_shares_fp = 0;
+                ctx.accounts.position.miss_shares_fp = 0;
+                scale_void_refund_fp(void_fp, ctx.accounts.market.void_payout_ratio_fp)?
+            }
+            Outcome::Unresolved => unreachable!(),
+        };
+        if redeem_fp <= 0 {
+            return Ok(());
+        }
+
+        // Pay from vault to user (no &mut market held while doing CPI)
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        token::transfer(
+            CpiContext::new_with_signer(
+                ctx.accounts.token_program.to_account_info(),
+                Transfer {
                     from: ctx.accounts.vault_usdc.to_account_info(),
                     to: ctx.accounts.user_usdc.to_account_info(),
                     authority: ctx.accounts.market.to_account_info(),
@@ -454,6 +1351,212 @@ pub mod milestone_amm {
         Ok(())
     }
 
+    /// After settlement, pay the authority's share of the surplus (everything not reserved
+    /// for redemptions, fee buckets or LPs). Once no winning shares remain outstanding this
+    /// is a final sweep of whatever else is left in the vault.
+    pub fn withdraw_residual(ctx: Context<WithdrawResidual>) -> Result<()> {
+        let withdrawer = ctx.accounts.withdrawer.key();
+        require!(
+            withdrawer == ctx.accounts.market.authority
+                || ctx.accounts.market.residual_recipient == Some(withdrawer),
+            AmmError::Unauthorized
+        );
+        require!(ctx.accounts.market.outcome != Outcome::Unresolved, AmmError::Unsettled);
+
+        let vault_amount = ctx.accounts.vault_usdc.amount;
+        ctx.accounts.market.snapshot_residual(vault_amount)?;
+        let m = &ctx.accounts.market;
+        let final_sweep = m.redemption_obligations_fp()? == 0;
+        let mut amount_fp = m.authority_residual_fp;
+        if final_sweep {
+            let reserved = m
+                .lp_pool_fp
+                .checked_add(m.sponsor_pool_fp)
+                .and_then(|v| v.checked_add(m.accrued_fees_fp))
+                .and_then(|v| v.checked_add(m.creator_fees_fp))
+                .and_then(|v| v.checked_add(m.bond_escrow_fp))
+                .ok_or(AmmError::MathOverflow)?;
+            amount_fp = amount_fp.max((vault_amount as i128).saturating_sub(reserved));
+        }
+        require!(amount_fp > 0, AmmError::InvalidAmount);
+
+        let pda_authority = m.authority;
+        let bump = m.bump;
+        let milestone_id = m.milestone_id.clone();
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        vault_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_usdc,
+            ctx.accounts.recipient_usdc.to_account_info(),
+            ctx.accounts.market.to_account_info(),
+            &seeds,
+            amount_fp as u64,
+        )?;
+
+        let m = &mut ctx.accounts.market;
+        m.authority_residual_fp = 0;
+        emit!(ResidualWithdrawn {
+            market: m.key(),
+            recipient: ctx.accounts.recipient_usdc.key(),
+            amount_fp: amount_fp as u64,
+            final_sweep,
+        });
+        Ok(())
+    }
+
+    /// Pay out a fee bucket retained in the vault. Never touches collateral.
+    /// Protocol fees: authority or treasury wallet; creator fees: authority only.
+    pub fn withdraw_fees(ctx: Context<WithdrawFees>, bucket: FeeBucket, amount_fp: u64) -> Result<()> {
+        let withdrawer = ctx.accounts.withdrawer.key();
+        let is_authority = withdrawer == ctx.accounts.market.authority;
+        let available_fp = match bucket {
+            FeeBucket::Protocol => {
+                require!(
+                    is_authority || ctx.accounts.market.treasury == Some(withdrawer),
+                    AmmError::Unauthorized
+                );
+                ctx.accounts.market.accrued_fees_fp
+            }
+            FeeBucket::Creator => {
+                require!(is_authority, AmmError::Unauthorized);
+                ctx.accounts.market.creator_fees_fp
+            }
+        };
+        require!(amount_fp > 0, AmmError::InvalidAmount);
+        require!((amount_fp as i128) <= available_fp, AmmError::InsufficientFees);
+
+        let pda_authority = ctx.accounts.market.authority;
+        let bump = ctx.accounts.market.bump;
+        let milestone_id = ctx.accounts.market.milestone_id.clone();
+        let seeds = [
+            SEED_MARKET,
+            pda_authority.as_ref(),
+            milestone_id.as_ref(),
+            &[bump],
+        ];
+        vault_transfer(
+            &ctx.accounts.token_program,
+            &ctx.accounts.vault_usdc,
+            ctx.accounts.recipient_usdc.to_account_info(),
+            ctx.accounts.market.to_account_info(),
+            &seeds,
+            amount_fp,
+        )?;
+
+        let m = &mut ctx.accounts.market;
+        let bucket_fp = match bucket {
+            FeeBucket::Protocol => &mut m.accrued_fees_fp,
+            FeeBucket::Creator => &mut m.creator_fees_fp,
+        };
+        *bucket_fp = bucket_fp
+            .checked_sub(amount_fp as i128)
+            .ok_or(AmmError::MathOverflow)?;
+        emit!(FeesWithdrawn {
+            market: m.key(),
+            bucket,
+            recipient: ctx.accounts.recipient_usdc.key(),
+            amount_fp,
+        });
+        Ok(())
+    }
+
+    /// Queue a new M-of-N oracle quorum, M > N/2 (empty set and threshold 0 disables it), applied
+    /// through `admin_apply_oracle_update` like a signer change. Once enabled, settlement goes
+    /// through `submit_oracle_vote` only.
+    pub fn admin_queue_oracle_quorum(
+        ctx: Context<AdminAuth>,
+        oracles: Vec<Pubkey>,
+        threshold: u8,
+    ) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
+        validate_oracle_quorum(&oracles, threshold)?;
+        queue_oracle_change(&mut ctx.accounts.market, OracleChange::Quorum { oracles, threshold })
+    }
+
+    /// Set (or clear with `None`) the price-feed resolver used by `resolve_from_feed`.
+    pub fn admin_set_feed_resolver(ctx: Context<AdminAuth>, resolver: Option<FeedResolver>) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
+        require!(
+            Clock::get()?.unix_timestamp < ctx.accounts.market.deadline_ts,
+            AmmError::AfterDeadline
+        );
+        require!(ctx.accounts.market.pending_oracle_update.is_none(), AmmError::OracleUpdatePending);
+        if let Some(r) = resolver {
+            // A price published up to max_staleness after the deadline must still be readable
+            // once the settlement window opens
+            require!(
+                r.max_staleness_secs > ctx.accounts.market.grace_period_secs && r.max_conf_bps <= 10_000,
+                AmmError::InvalidUpdate
+            );
+        }
+        let m = &mut ctx.accounts.market;
+        m.feed_resolver = resolver;
+        emit!(FeedResolverSet {
+            market: m.key(),
+            resolver,
+        });
+        Ok(())
+    }
+
+    /// Replace the b schedule (empty clears it). Points must be strictly increasing in time.
+    /// Scheduled b is never funded: a trade that would move b onto a raise the vault does not
+    /// cover at that moment fails (see `apply_b_schedule`). Not combinable with `auto_b`.
+    pub fn admin_set_b_schedule(ctx: Context<AdminAuth>, points: Vec<BPoint>) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
+        require!(ctx.accounts.market.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(points.is_empty() || !ctx.accounts.market.auto_b, AmmError::BModeConflict);
+        require!(points.len() <= MAX_B_SCHEDULE_POINTS, AmmError::InvalidSchedule);
+        for (i, p) in points.iter().enumerate() {
+            require!((MIN_B_FP..=MAX_B_FP).contains(&(p.b_fp as i128)), AmmError::InvalidB);
+            if i > 0 {
+                require!(p.ts > points[i - 1].ts, AmmError::InvalidSchedule);
+            }
+        }
+        let m = &mut ctx.accounts.market;
+        m.b_schedule = points;
+        emit!(BScheduleSet {
+            market: m.key(),
+            points: m.b_schedule.clone(),
+        });
+        Ok(())
+    }
+
+    /// Queue a change of `oracle_signer` (`None` removes it), applicable after
+    /// `ORACLE_UPDATE_NOTICE_SECS` and only before the deadline. Replaces any queued change.
+    pub fn admin_queue_oracle_update(ctx: Context<AdminAuth>, new_oracle: Option<Pubkey>) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
+        queue_oracle_change(&mut ctx.accounts.market, OracleChange::Signer(new_oracle))
+    }
+
+    /// Apply the queued oracle change (signer or quorum) once its notice period has passed.
+    pub fn admin_apply_oracle_update(ctx: Context<AdminAuth>) -> Result<()> {
+        require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
+        let clock = Clock::get()?;
+        let m = &mut ctx.accounts.market;
+        require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+        require!(clock.unix_timestamp < m.deadline_ts, AmmError::AfterDeadline);
+        let upd = m.pending_oracle_update.take().ok_or(AmmError::NoPendingOracleUpdate)?;
+        require!(clock.unix_timestamp >= upd.eta_ts, AmmError::OracleUpdateNotReady);
+
+        match &upd.change {
+            OracleChange::Signer(new_oracle) => m.oracle_signer = *new_oracle,
+            OracleChange::Quorum { oracles, threshold } => {
+                m.oracles = oracles.clone();
+                m.oracle_threshold = *threshold;
+            }
+        }
+        emit!(OracleUpdateApplied {
+            market: m.key(),
+            change: upd.change,
+        });
+        Ok(())
+    }
+
     pub fn admin_set_paused(ctx: Context<AdminAuth>, paused: bool) -> Result<()> {
         require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
         let m = &mut ctx.accounts.market;
@@ -462,22 +1565,34 @@ pub mod milestone_amm {
         Ok(())
     }
 
-    pub fn admin_update_params(ctx: Context<AdminAuth>, upd: UpdateParams) -> Result<()> {
+    pub fn admin_update_params(ctx: Context<AdminUpdate>, upd: UpdateParams) -> Result<()> {
         require!(ctx.accounts.authority.key() == ctx.accounts.market.authority, AmmError::Unauthorized);
-        let m = &mut ctx.accounts.market;
+        // b has one driver: set by hand (here or a sponsor's raise), by auto_b, or by the schedule
+        let auto_b = upd.auto_b.unwrap_or(ctx.accounts.market.auto_b);
+        require!(!auto_b || ctx.accounts.market.b_schedule.is_empty(), AmmError::BModeConflict);
         if let Some(b_fp) = upd.b_fp {
+            require_manual_b(&ctx.accounts.market, auto_b)?;
             require!(b_fp >= 10_000 && b_fp <= 1_000_000_000_000, AmmError::InvalidB);
-            m.b_fp = b_fp as i128;
+            update_b_preserving_price(ctx.accounts, b_fp as i128)?;
         }
+        let m = &mut ctx.accounts.market;
         if let Some(fee_bps) = upd.fee_bps {
             require!(fee_bps <= 10_000, AmmError::InvalidFee);
+            validate_fee_model(m.fee_model, fee_bps)?;
             m.fee_bps = fee_bps;
         }
         if let Some(deadline_ts) = upd.deadline_ts {
             require!(deadline_ts >= m.deadline_ts, AmmError::InvalidUpdate);
+            require!(Clock::get()?.unix_timestamp < m.deadline_ts, AmmError::AfterDeadline);
+            // A queued oracle change must land before the deadline it was checked against
+            require!(m.pending_oracle_update.is_none(), AmmError::OracleUpdatePending);
             m.deadline_ts = deadline_ts;
         }
         if let Some(grace) = upd.grace_period_secs {
+            require!(
+                !matches!(m.feed_resolver, Some(r) if r.max_staleness_secs <= grace),
+                AmmError::InvalidUpdate
+            );
             m.grace_period_secs = grace;
         }
         if let Some(max_trade) = upd.max_trade_usdc_fp {
@@ -486,11 +1601,65 @@ pub mod milestone_amm {
         if let Some(max_pos) = upd.max_position_shares_fp {
             m.max_position_shares_fp = max_pos as i128;
         }
+        if let Some(max_sell_shares) = upd.max_sell_shares_fp {
+            m.max_sell_shares_fp = max_sell_shares as i128;
+        }
+        if let Some(max_sell_usdc) = upd.max_sell_usdc_fp {
+            m.max_sell_usdc_fp = max_sell_usdc as i128;
+        }
+        if let Some(max_side) = upd.max_side_shares_fp {
+            m.max_side_shares_fp = max_side as i128;
+        }
+        if let Some(max_imbalance) = upd.max_imbalance_fp {
+            m.max_imbalance_fp = max_imbalance as i128;
+        }
+        if let Some(interval) = upd.min_trade_interval_secs {
+            require!(interval >= 0, AmmError::InvalidUpdate);
+            m.min_trade_interval_secs = interval;
+        }
+        if let Some(max_trades) = upd.max_trades_per_window {
+            m.max_trades_per_window = max_trades;
+        }
+        if let Some(window) = upd.trade_window_secs {
+            require!(window >= 0, AmmError::InvalidUpdate);
+            m.trade_window_secs = window;
+        }
+        // A trade cap without a window would reset on every trade
+        require!(
+            m.max_trades_per_window == 0 || m.trade_window_secs > 0,
+            AmmError::InvalidUpdate
+        );
         if let Some(treasury) = upd.treasury {
             m.treasury = Some(treasury);
         }
-        if let Some(oracle) = upd.oracle_signer {
-            m.oracle_signer = Some(oracle);
+        if let Some(referral_bps) = upd.referral_fee_bps {
+            require!(referral_bps <= 10_000, AmmError::InvalidFee);
+            m.referral_fee_bps = Some(referral_bps);
+        }
+        if let Some(auto_b) = upd.auto_b {
+            m.auto_b = auto_b;
+        }
+        if let Some(bond) = upd.settlement_bond_fp {
+            require!(m.proposal.is_none(), AmmError::ProposalExists);
+            m.settlement_bond_fp = bond as i128;
+        }
+        if let Some(window) = upd.challenge_window_secs {
+            require!(window >= 0 && m.proposal.is_none(), AmmError::InvalidUpdate);
+            m.challenge_window_secs = window;
+        }
+        // Without a challenge window a proposal could be finalized in the same slot
+        require!(
+            m.settlement_bond_fp == 0 || m.challenge_window_secs > 0,
+            AmmError::InvalidUpdate
+        );
+        if let Some(timeout) = upd.settlement_timeout_secs {
+            require!(timeout >= MIN_SETTLEMENT_TIMEOUT_SECS, AmmError::InvalidUpdate);
+            // Settlers and traders plan around the timeout in force when trading closed
+            require!(Clock::get()?.unix_timestamp < m.deadline_ts, AmmError::AfterDeadline);
+            m.settlement_timeout_secs = timeout;
+        }
+        if let Some(recipient) = upd.residual_recipient {
+            m.residual_recipient = Some(recipient);
         }
         Ok(())
     }
@@ -498,6 +1667,66 @@ pub mod milestone_amm {
 
 /// ========== Accounts ==========
 
+#[derive(Accounts)]
+pub struct InitConfig<'info> {
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        seeds = [SEED_CONFIG],
+        bump,
+        space = 8 + Config::SIZE
+    )]
+    pub config: Account<'info, Config>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()))]
+    pub program: Program<'info, crate::program::MilestoneAmm>,
+
+    /// Only the program's upgrade authority may create the config
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ AmmError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(mut, seeds = [SEED_CONFIG], bump = config.bump)]
+    pub config: Account<'info, Config>,
+}
+
+#[derive(Accounts)]
+pub struct SetVolumeTracking<'info> {
+    pub admin: Signer<'info>,
+
+    #[account(seeds = [SEED_CONFIG], bump = config.bump)]
+    pub config: Account<'info, Config>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct InitTraderVolume<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(
+        init,
+        payer = user,
+        seeds = [SEED_VOLUME, user.key().as_ref()],
+        bump,
+        space = 8 + TraderVolume::SIZE
+    )]
+    pub trader_volume: Account<'info, TraderVolume>,
+
+    pub system_program: Program<'info, System>,
+}
+
 #[derive(Accounts)]
 #[instruction(params: InitParams, milestone_id: Vec<u8>)]
 pub struct InitMarket<'info> {
@@ -524,6 +1753,21 @@ pub struct InitMarket<'info> {
     )]
     pub market: Account<'info, Market>,
 
+    /// LP share mint (market PDA is mint authority)
+    #[account(
+        init,
+        payer = authority,
+        seeds = [SEED_LP_MINT, market.key().as_ref()],
+        bump,
+        mint::decimals = usdc_mint.decimals,
+        mint::authority = market
+    )]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// Optional protocol config (supplies the default fee split and the minimum protocol share)
+    #[account(seeds = [SEED_CONFIG], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
     pub system_program: Program<'info, System>,
     pub associated_token_program: Program<'info, AssociatedToken>,
     pub token_program: Program<'info, Token>,
@@ -551,6 +1795,130 @@ pub struct SeedLiquidity<'info> {
     pub token_program: Program<'info, Token>,
 }
 
+#[derive(Accounts)]
+pub struct AddLiquidity<'info> {
+    #[account(mut)]
+    pub provider: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = provider_usdc.owner == provider.key(),
+        constraint = provider_usdc.mint == market.usdc_mint
+    )]
+    pub provider_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    /// Provider LP token ATA (created if needed)
+    #[account(
+        init_if_needed,
+        payer = provider,
+        associated_token::mint = lp_mint,
+        associated_token::authority = provider
+    )]
+    pub provider_lp: Account<'info, TokenAccount>,
+
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveLiquidity<'info> {
+    pub provider: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = provider_usdc.owner == provider.key(),
+        constraint = provider_usdc.mint == market.usdc_mint
+    )]
+    pub provider_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.lp_mint)]
+    pub lp_mint: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        constraint = provider_lp.owner == provider.key(),
+        constraint = provider_lp.mint == lp_mint.key()
+    )]
+    pub provider_lp: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct SponsorLiquidity<'info> {
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = sponsor_usdc.owner == sponsor.key(),
+        constraint = sponsor_usdc.mint == market.usdc_mint
+    )]
+    pub sponsor_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    /// Sponsorship PDA for (market, sponsor)
+    #[account(
+        init_if_needed,
+        payer = sponsor,
+        seeds = [SEED_SPONSORSHIP, market.key().as_ref(), sponsor.key().as_ref()],
+        bump,
+        space = 8 + Sponsorship::SIZE
+    )]
+    pub sponsorship: Account<'info, Sponsorship>,
+
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimSponsorRefund<'info> {
+    pub sponsor: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [SEED_SPONSORSHIP, market.key().as_ref(), sponsor.key().as_ref()],
+        bump = sponsorship.bump
+    )]
+    pub sponsorship: Account<'info, Sponsorship>,
+
+    #[account(
+        mut,
+        constraint = sponsor_usdc.owner == sponsor.key(),
+        constraint = sponsor_usdc.mint == market.usdc_mint
+    )]
+    pub sponsor_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
 #[derive(Accounts)]
 pub struct Trade<'info> {
     /// User placing trade
@@ -582,9 +1950,23 @@ pub struct Trade<'info> {
     )]
     pub position: Account<'info, Position>,
 
-    /// Optional treasury ATA (required iff market.treasury.is_some())
+    /// Treasury USDC account; required iff market.treasury.is_some(), and must be
+    /// owned by market.treasury with the market's mint
     #[account(mut)]
-    pub treasury_usdc: Account<'info, TokenAccount>,
+    pub treasury_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Optional protocol config (supplies the default referral share)
+    #[account(seeds = [SEED_CONFIG], bump = config.bump)]
+    pub config: Option<Account<'info, Config>>,
+
+    /// Optional referrer USDC account that receives a share of the fee (unauthenticated)
+    #[account(mut, constraint = referrer_usdc.mint == market.usdc_mint @ AmmError::WrongMint)]
+    pub referrer_usdc: Option<Account<'info, TokenAccount>>,
+
+    /// Optional trader volume account; with `config`, enables fee-tier discounts (volume is
+    /// only recorded on markets the config admin approved)
+    #[account(mut, seeds = [SEED_VOLUME, user.key().as_ref()], bump = trader_volume.bump)]
+    pub trader_volume: Option<Account<'info, TraderVolume>>,
 
     pub token_program: Program<'info, Token>,
     pub system_program: Program<'info, System>,
@@ -595,33 +1977,207 @@ pub struct Settle<'info> {
     #[account(mut)]
     pub market: Account<'info, Market>,
 
-    /// Either authority signer …
-    pub authority: Option<Signer<'info>>,
-    /// …or oracle signer if configured
-    pub oracle_signer: Option<Signer<'info>>,
+    /// Either authority signer …
+    pub authority: Option<Signer<'info>>,
+    /// …or oracle signer if configured
+    pub oracle_signer: Option<Signer<'info>>,
+
+    /// CHECK: instructions sysvar, read to verify a relayed oracle attestation
+    #[account(address = ix_sysvar::ID)]
+    pub instructions: Option<UncheckedAccount<'info>>,
+
+    /// Pays for the settlement record (the settler or a relayer)
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(
+        init,
+        payer = payer,
+        seeds = [SEED_SETTLEMENT, market.key().as_ref()],
+        bump,
+        space = 8 + SettlementRecord::SIZE
+    )]
+    pub settlement_record: Account<'info, SettlementRecord>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct EarlyResolve<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Either authority signer …
+    pub authority: Option<Signer<'info>>,
+    /// …or oracle signer if configured
+    pub oracle_signer: Option<Signer<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitOracleVote<'info> {
+    #[account(mut)]
+    pub oracle: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Vote tally PDA for the market
+    #[account(
+        init_if_needed,
+        payer = oracle,
+        seeds = [SEED_VOTES, market.key().as_ref()],
+        bump,
+        space = 8 + SettlementVotes::SIZE
+    )]
+    pub votes: Account<'info, SettlementVotes>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PostBond<'info> {
+    /// Proposer or disputer posting the bond
+    pub bonder: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = bonder_usdc.owner == bonder.key(),
+        constraint = bonder_usdc.mint == market.usdc_mint
+    )]
+    pub bonder_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeProposal<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeEarlyResolution<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct CancelEarlyResolution<'info> {
+    pub authority: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Vote tally PDA, required when the market settles through a quorum
+    #[account(mut, seeds = [SEED_VOTES, market.key().as_ref()], bump = votes.bump)]
+    pub votes: Option<Account<'info, SettlementVotes>>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveFromFeed<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// CHECK: must be the resolver's feed; parsed as a Pyth v2 price account
+    #[account(owner = PYTH_PROGRAM_ID @ AmmError::InvalidFeed)]
+    pub feed: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ForceVoid<'info> {
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimBond<'info> {
+    pub claimant: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        constraint = claimant_usdc.owner == claimant.key(),
+        constraint = claimant_usdc.mint == market.usdc_mint
+    )]
+    pub claimant_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct Redeem<'info> {
+    #[account(mut)]
+    pub user: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    #[account(
+        mut,
+        seeds = [SEED_POSITION, market.key().as_ref(), user.key().as_ref()],
+        bump
+    )]
+    pub position: Account<'info, Position>,
+
+    #[account(
+        mut,
+        constraint = user_usdc.owner == user.key(),
+        constraint = user_usdc.mint == market.usdc_mint
+    )]
+    pub user_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
 }
 
 #[derive(Accounts)]
-pub struct Redeem<'info> {
-    #[account(mut)]
-    pub user: Signer<'info>,
+pub struct WithdrawFees<'info> {
+    /// Market authority or treasury wallet
+    pub withdrawer: Signer<'info>,
 
     #[account(mut)]
     pub market: Account<'info, Market>,
 
     #[account(
         mut,
-        seeds = [SEED_POSITION, market.key().as_ref(), user.key().as_ref()],
-        bump
+        constraint = recipient_usdc.owner == withdrawer.key(),
+        constraint = recipient_usdc.mint == market.usdc_mint
     )]
-    pub position: Account<'info, Position>,
+    pub recipient_usdc: Account<'info, TokenAccount>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Account<'info, TokenAccount>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawResidual<'info> {
+    /// Market authority or configured residual recipient
+    pub withdrawer: Signer<'info>,
+
+    #[account(mut)]
+    pub market: Account<'info, Market>,
 
     #[account(
         mut,
-        constraint = user_usdc.owner == user.key(),
-        constraint = user_usdc.mint == market.usdc_mint
+        constraint = recipient_usdc.owner == withdrawer.key(),
+        constraint = recipient_usdc.mint == market.usdc_mint
     )]
-    pub user_usdc: Account<'info, TokenAccount>,
+    pub recipient_usdc: Account<'info, TokenAccount>,
 
     #[account(mut, address = market.vault_usdc)]
     pub vault_usdc: Account<'info, TokenAccount>,
@@ -636,8 +2192,80 @@ pub struct AdminAuth<'info> {
     pub market: Account<'info, Market>,
 }
 
+#[derive(Accounts)]
+pub struct AdminUpdate<'info> {
+    pub authority: Signer<'info>,
+    #[account(mut)]
+    pub market: Account<'info, Market>,
+
+    /// Authority USDC account; required when a b change tops up or releases subsidy
+    #[account(
+        mut,
+        constraint = authority_usdc.owner == authority.key(),
+        constraint = authority_usdc.mint == market.usdc_mint
+    )]
+    pub authority_usdc: Option<Account<'info, TokenAccount>>,
+
+    #[account(mut, address = market.vault_usdc)]
+    pub vault_usdc: Option<Account<'info, TokenAccount>>,
+
+    pub token_program: Option<Program<'info, Token>>,
+}
+
 /// ========== State ==========
 
+#[account]
+pub struct Config {
+    pub admin: Pubkey,
+    pub referral_fee_bps: u16,
+    pub bump: u8,
+    /// Default fee split for markets initialized without one
+    pub fee_split: FeeSplit,
+    /// Volume discount tiers, ascending by threshold; unused slots have discount_bps == 0
+    pub fee_tiers: [FeeTier; MAX_FEE_TIERS],
+}
+impl Config {
+    pub const SIZE: usize = 32 + 2 + 1 + FeeSplit::SIZE + FeeTier::SIZE * MAX_FEE_TIERS;
+}
+
+/// Per-wallet trading volume over a rolling window of daily buckets.
+#[account]
+pub struct TraderVolume {
+    pub owner: Pubkey,
+    pub bump: u8,
+    /// Day index (unix_ts / 86_400) of the newest bucket
+    pub last_day: i64,
+    /// Bucket for day `d` lives at `d % VOLUME_WINDOW_DAYS`
+    pub daily_volume_fp: [u64; VOLUME_WINDOW_DAYS],
+}
+impl TraderVolume {
+    pub const SIZE: usize = 32 + 1 + 8 + 8 * VOLUME_WINDOW_DAYS;
+
+    /// Sum of the buckets that fall within the window ending today.
+    fn rolling_volume_fp(&self, now: i64) -> u64 {
+        let today = now / SECS_PER_DAY;
+        (0..VOLUME_WINDOW_DAYS as i64)
+            .map(|k| self.last_day - k)
+            .filter(|d| *d > today - VOLUME_WINDOW_DAYS as i64)
+            .fold(0u64, |acc, d| {
+                acc.saturating_add(self.daily_volume_fp[d.rem_euclid(VOLUME_WINDOW_DAYS as i64) as usize])
+            })
+    }
+
+    /// Clear buckets that aged out, then add `usdc_fp` to today's.
+    fn record(&mut self, now: i64, usdc_fp: u64) {
+        let today = now / SECS_PER_DAY;
+        let gap = (today - self.last_day).clamp(0, VOLUME_WINDOW_DAYS as i64);
+        for k in 1..=gap {
+            let idx = (self.last_day + k).rem_euclid(VOLUME_WINDOW_DAYS as i64) as usize;
+            self.daily_volume_fp[idx] = 0;
+        }
+        self.last_day = self.last_day.max(today);
+        let idx = today.rem_euclid(VOLUME_WINDOW_DAYS as i64) as usize;
+        self.daily_volume_fp[idx] = self.daily_volume_fp[idx].saturating_add(usdc_fp);
+    }
+}
+
 #[account]
 pub struct Market {
     pub authority: Pubkey,
@@ -658,11 +2286,255 @@ pub struct Market {
     pub liquidity_usdc_fp: i128,
     pub oracle_signer: Option<Pubkey>,
     pub bump: u8,
+    pub max_sell_shares_fp: i128,
+    pub max_sell_usdc_fp: i128,
+    /// Cap on total outstanding shares per side (0 = uncapped)
+    pub max_side_shares_fp: i128,
+    /// Cap on |q_hit - q_miss| (0 = uncapped)
+    pub max_imbalance_fp: i128,
+    /// Min seconds between trades of one wallet (0 = no cooldown)
+    pub min_trade_interval_secs: i64,
+    /// Max trades per wallet per `trade_window_secs` (0 = unlimited)
+    pub max_trades_per_window: u32,
+    pub trade_window_secs: i64,
+    /// Share of each fee (bps) paid to a referrer; None falls back to Config
+    pub referral_fee_bps: Option<u16>,
+    pub fee_model: FeeModel,
+    /// Protocol fees retained in the vault (no treasury) and not yet withdrawn
+    pub accrued_fees_fp: i128,
+    pub fee_split: FeeSplit,
+    /// Creator fees held in the vault for the market authority
+    pub creator_fees_fp: i128,
+    /// LP fees left in the pool
+    pub lp_fees_fp: i128,
+    pub lp_mint: Pubkey,
+    /// Subsidy contributed through `add_liquidity` (subset of liquidity_usdc_fp)
+    pub lp_liquidity_fp: i128,
+    /// Shares held by positions (what redemption owes), independent of curve q
+    pub hit_shares_outstanding_fp: i128,
+    pub miss_shares_outstanding_fp: i128,
+    /// Set once the post-settlement residual has been divided
+    pub residual_snapshotted: bool,
+    /// LP share of the residual not yet paid out
+    pub lp_pool_fp: i128,
+    /// Authority share of the residual not yet withdrawn
+    pub authority_residual_fp: i128,
+    /// Optional wallet allowed to withdraw the authority's residual
+    pub residual_recipient: Option<Pubkey>,
+    /// Subsidy contributed through `sponsor_liquidity` (subset of liquidity_usdc_fp)
+    pub sponsor_liquidity_fp: i128,
+    /// Sponsor contributions not yet refunded (denominator for pro-rata refunds)
+    pub sponsor_unclaimed_fp: i128,
+    /// Sponsor share of the residual not yet refunded
+    pub sponsor_pool_fp: i128,
+    /// Keep b at liquidity / ln 2 whenever liquidity is added
+    pub auto_b: bool,
+    /// Piecewise-linear b over time; when set, every trade first moves b onto it
+    pub b_schedule: Vec<BPoint>,
+    /// M-of-N settlement oracles (threshold 0 = quorum disabled)
+    pub oracles: Vec<Pubkey>,
+    pub oracle_threshold: u8,
+    /// Bond to propose/dispute an outcome (0 = optimistic settlement disabled)
+    pub settlement_bond_fp: i128,
+    pub challenge_window_secs: i64,
+    pub proposal: Option<SettlementProposal>,
+    /// Proposal bonds held in the vault
+    pub bond_escrow_fp: i128,
+    /// How positions are refunded if the market settles `Void`
+    pub void_refund: VoidRefund,
+    /// Sum of positive position cost bases (Void refund obligations under `CostBasis`)
+    pub cost_basis_outstanding_fp: i128,
+    /// Fixed-point payout per HIT share once settled (1 for Hit, 0 for Miss, the settled
+    /// fraction for Partial); MISS shares pay one minus it
+    pub hit_payout_fp: i128,
+    /// Highest oracle attestation nonce accepted (replay protection)
+    pub attestation_nonce: u64,
+    pub settlement_timeout_secs: i64,
+    /// Settled by `force_void` rather than by any resolver
+    pub force_voided: bool,
+    /// Early resolution pending: trading halted, `early_outcome` settles after the delay
+    pub early_resolution: bool,
+    pub early_resolution_ts: i64,
+    pub early_outcome: Outcome,
+    /// Timelocked `oracle_signer` change
+    pub pending_oracle_update: Option<OracleUpdate>,
+    /// Price-feed resolver for quantitative milestones
+    pub feed_resolver: Option<FeedResolver>,
+    /// Approved by the config admin to count toward traders' fee-tier volume
+    pub volume_tracked: bool,
+    /// Fraction of each Void refund paid out (FP_SCALER = in full), fixed with the residual
+    /// snapshot so every holder takes the same haircut
+    pub void_payout_ratio_fp: i128,
 }
 impl Market {
     // conservative bound; adjust if Anchor complains about space
     pub const SIZE: usize =
-        32 + 32 + 32 + 16 + 2 + 8 + 8 + 1 + 16 + 16 + 1 + 16 + 16 + 1 + 4 + 64 + 16 + 1 + 32 + 1;
+        32 + 32 + 32 + 16 + 2 + 8 + 8 + 1 + 16 + 16 + 1 + 16 + 16 + 1 + 4 + 64 + 16 + 1 + 32 + 1
+        + 16 + 16 + 16 + 16 + 8 + 4 + 8 + 3 + FeeModel::SIZE + 16
+        + FeeSplit::SIZE + 16 + 16
+        + 32 + 16 + 16 + 16 + 1 + 16
+        + 16 + 1 + 32
+        + 16 + 16 + 16 + 1
+        + 4 + BPoint::SIZE * MAX_B_SCHEDULE_POINTS
+        + 4 + 32 * MAX_ORACLES + 1
+        + 16 + 8 + 1 + SettlementProposal::SIZE + 16
+        + VoidRefund::SIZE + 16
+        + 16
+        + 8
+        + 8 + 1
+        + 1 + 8 + 1
+        + 1 + OracleUpdate::SIZE
+        + 1 + FeedResolver::SIZE
+        + 1
+        + 16;
+
+    /// USDC still owed to position holders at the settled outcome.
+    fn redemption_obligations_fp(&self) -> Result<i128> {
+        match self.outcome {
+            Outcome::Hit => Ok(self.hit_shares_outstanding_fp),
+            Outcome::Miss => Ok(self.miss_shares_outstanding_fp),
+            Outcome::Partial => split_payout_fp(
+                self.hit_shares_outstanding_fp,
+                self.miss_shares_outstanding_fp,
+                self.hit_payout_fp,
+            ),
+            Outcome::Void => scale_void_refund_fp(self.void_refund_owed_fp()?, self.void_payout_ratio_fp),
+            Outcome::Unresolved => err!(AmmError::Unsettled),
+        }
+    }
+
+    /// Full Void refunds of every outstanding position, before any haircut.
+    fn void_refund_owed_fp(&self) -> Result<i128> {
+        void_refund_fp(
+            self.void_refund,
+            self.hit_shares_outstanding_fp,
+            self.miss_shares_outstanding_fp,
+            self.cost_basis_outstanding_fp,
+        )
+    }
+
+    /// Vault balance not reserved for redemptions, unwithdrawn protocol/creator fees or
+    /// escrowed settlement bonds.
+    fn residual_surplus_fp(&self, vault_amount: u64) -> Result<i128> {
+        let reserved = self
+            .redemption_obligations_fp()?
+            .checked_add(self.accrued_fees_fp)
+            .and_then(|v| v.checked_add(self.creator_fees_fp))
+            .and_then(|v| v.checked_add(self.bond_escrow_fp))
+            .ok_or(AmmError::MathOverflow)?;
+        Ok((vault_amount as i128).saturating_sub(reserved).max(0))
+    }
+
+    /// Divide the post-settlement surplus once: LPs get their capital-weighted share of
+    /// it plus all LP fees, sponsors their capital-weighted share, the authority the rest.
+    /// The surplus is stable after settlement (redemptions, fee withdrawals and bond claims
+    /// shrink the vault and the reserve equally), so it is fixed on first use. A Void market
+    /// whose vault cannot refund everyone in full fixes the pro-rata haircut here too.
+    fn snapshot_residual(&mut self, vault_amount: u64) -> Result<()> {
+        if self.residual_snapshotted {
+            return Ok(());
+        }
+        if self.outcome == Outcome::Void {
+            // Refunds never dip into fee buckets or escrowed bonds
+            let available_fp = (vault_amount as i128)
+                .saturating_sub(self.accrued_fees_fp)
+                .saturating_sub(self.creator_fees_fp)
+                .saturating_sub(self.bond_escrow_fp);
+            self.void_payout_ratio_fp = void_payout_ratio_fp(available_fp, self.void_refund_owed_fp()?)?;
+        }
+        let surplus = self.residual_surplus_fp(vault_amount)?;
+        // LP fees belong to LPs only; with no LPs they fall into the shared base
+        let lp_fees = if self.lp_liquidity_fp > 0 {
+            self.lp_fees_fp.clamp(0, surplus)
+        } else {
+            0
+        };
+        let base = surplus - lp_fees;
+        let capital_share = |contributed: i128| -> Result<i128> {
+            base.checked_mul(contributed)
+                .ok_or(AmmError::MathOverflow)?
+                .checked_div(self.liquidity_usdc_fp.max(1))
+                .ok_or(error!(AmmError::MathOverflow))
+        };
+        let lp_pool_fp = capital_share(self.lp_liquidity_fp)?
+            .checked_add(lp_fees)
+            .ok_or(AmmError::MathOverflow)?;
+        let sponsor_pool_fp = capital_share(self.sponsor_liquidity_fp)?;
+        self.lp_pool_fp = lp_pool_fp;
+        self.sponsor_pool_fp = sponsor_pool_fp;
+        self.authority_residual_fp = surplus - lp_pool_fp - sponsor_pool_fp;
+        self.residual_snapshotted = true;
+        Ok(())
+    }
+
+    /// Fee buckets and escrowed bonds the vault holds for others while the market trades.
+    fn fee_and_bond_reserve_fp(&self) -> Result<i128> {
+        self.accrued_fees_fp
+            .checked_add(self.creator_fees_fp)
+            .and_then(|v| v.checked_add(self.lp_fees_fp))
+            .and_then(|v| v.checked_add(self.bond_escrow_fp))
+            .ok_or(error!(AmmError::MathOverflow))
+    }
+
+    /// Credit the retained parts of a routed fee to their buckets.
+    fn accrue_fees(&mut self, routed: &RoutedFee) -> Result<()> {
+        self.accrued_fees_fp = self
+            .accrued_fees_fp
+            .checked_add(routed.protocol_retained_fp)
+            .ok_or(AmmError::MathOverflow)?;
+        self.creator_fees_fp = self
+            .creator_fees_fp
+            .checked_add(routed.creator_fp)
+            .ok_or(AmmError::MathOverflow)?;
+        self.lp_fees_fp = self
+            .lp_fees_fp
+            .checked_add(routed.lp_fp)
+            .ok_or(AmmError::MathOverflow)?;
+        Ok(())
+    }
+}
+
+#[account]
+pub struct Sponsorship {
+    pub sponsor: Pubkey,
+    pub market: Pubkey,
+    /// Total USDC contributed
+    pub amount_fp: i128,
+    pub refunded: bool,
+    pub bump: u8,
+}
+impl Sponsorship {
+    pub const SIZE: usize = 32 + 32 + 16 + 1 + 1;
+}
+
+/// Quorum votes for a market's outcome.
+#[account]
+pub struct SettlementVotes {
+    pub market: Pubkey,
+    pub votes: Vec<OracleVote>,
+    /// Set once two current oracles disagreed
+    pub conflicted: bool,
+    pub bump: u8,
+}
+impl SettlementVotes {
+    pub const SIZE: usize = 32 + 4 + OracleVote::SIZE * MAX_ORACLES + 1 + 1;
+}
+
+/// Evidence behind a direct `settle_market`, kept for disputes and audits.
+#[account]
+pub struct SettlementRecord {
+    pub market: Pubkey,
+    pub outcome: Outcome,
+    pub hit_payout_fp: u64,
+    /// Authority or oracle whose decision this is (not the relayer)
+    pub settler: Pubkey,
+    pub settled_at: i64,
+    pub evidence_uri: String,
+    pub evidence_hash: [u8; 32],
+    pub bump: u8,
+}
+impl SettlementRecord {
+    pub const SIZE: usize = 32 + 1 + 8 + 32 + 8 + 4 + MAX_EVIDENCE_URI_LEN + 32 + 1;
 }
 
 #[account]
@@ -671,9 +2543,14 @@ pub struct Position {
     pub market: Pubkey,
     pub hit_shares_fp: i128,
     pub miss_shares_fp: i128,
+    pub last_trade_ts: i64,
+    pub window_start_ts: i64,
+    pub window_trades: u32,
+    /// USDC paid into the curve less USDC received from it (fees excluded)
+    pub net_cost_fp: i128,
 }
 impl Position {
-    pub const SIZE: usize = 32 + 32 + 16 + 16;
+    pub const SIZE: usize = 32 + 32 + 16 + 16 + 8 + 8 + 4 + 16;
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
@@ -681,6 +2558,22 @@ pub enum Outcome {
     Unresolved,
     Hit,
     Miss,
+    /// Milestone cancelled or ambiguous: positions are refunded per `VoidRefund`
+    Void,
+    /// Milestone partly achieved: HIT pays `hit_payout_fp`, MISS pays the rest
+    Partial,
+}
+
+/// Refund method for a `Void` outcome.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum VoidRefund {
+    /// Each position gets back its net cost basis (never below 0)
+    CostBasis,
+    /// Each Hit share pays `hit_fp`, each Miss share `FP_SCALER - hit_fp`
+    FixedSplit { hit_fp: u64 },
+}
+impl VoidRefund {
+    pub const SIZE: usize = 1 + 8;
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
@@ -689,8 +2582,171 @@ pub enum Side {
     Miss,
 }
 
+/// How `fee_bps` is turned into the fee charged on a trade.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeeModel {
+    /// `fee_bps` of ΔC
+    Flat,
+    /// `fee_bps · 4·p·(1−p)` of ΔC: the full fee at p = 0.5, tapering toward 0 and 1
+    ProbabilityScaled,
+    /// `fee_bps` until `ramp_secs` before the deadline, then linearly up to `max_fee_bps`
+    DeadlineRamp { max_fee_bps: u16, ramp_secs: i64 },
+}
+impl FeeModel {
+    pub const SIZE: usize = 1 + 2 + 8;
+}
+
+/// Split of the post-referral fee between protocol, market creator and LPs (sums to 10_000).
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FeeSplit {
+    pub protocol_bps: u16,
+    pub creator_bps: u16,
+    pub lp_bps: u16,
+}
+impl FeeSplit {
+    pub const SIZE: usize = 2 + 2 + 2;
+    /// Everything to the protocol (pre-split behavior)
+    pub const PROTOCOL_ONLY: FeeSplit = FeeSplit {
+        protocol_bps: 10_000,
+        creator_bps: 0,
+        lp_bps: 0,
+    };
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct OracleVote {
+    pub oracle: Pubkey,
+    pub outcome: Outcome,
+}
+impl OracleVote {
+    pub const SIZE: usize = 32 + 1;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SettlementProposal {
+    pub proposer: Pubkey,
+    pub outcome: Outcome,
+    pub proposed_at: i64,
+    pub bond_fp: i128,
+    pub disputer: Option<Pubkey>,
+    /// Proposer's claim made (or, outside a refund of both bonds, the whole pot paid out)
+    pub bonds_claimed: bool,
+    /// Disputer's bond refunded after a force-void or a Void/Partial ruling
+    pub disputer_bond_claimed: bool,
+}
+impl SettlementProposal {
+    pub const SIZE: usize = 32 + 1 + 8 + 16 + 33 + 1 + 1;
+}
+
+/// Off-chain oracle attestation relayed to `settle_market`. The oracle signs
+/// `market ‖ outcome (u8) ‖ hit_payout_fp (u64 LE) ‖ timestamp (i64 LE) ‖ nonce (u64 LE) ‖
+/// evidence_hash ‖ sha256(evidence_uri)`, so a relayer cannot swap the recorded evidence.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct OracleAttestation {
+    pub timestamp: i64,
+    pub nonce: u64,
+}
+
+/// Oracle configuration change that goes through the update queue.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub enum OracleChange {
+    /// New `oracle_signer` (`None` removes the oracle)
+    Signer(Option<Pubkey>),
+    /// New M-of-N quorum (empty set and threshold 0 disables it)
+    Quorum { oracles: Vec<Pubkey>, threshold: u8 },
+}
+impl OracleChange {
+    pub const SIZE: usize = 1 + (4 + 32 * MAX_ORACLES + 1);
+}
+
+/// Queued oracle change and when it can be applied.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, PartialEq, Eq)]
+pub struct OracleUpdate {
+    pub change: OracleChange,
+    pub eta_ts: i64,
+}
+impl OracleUpdate {
+    pub const SIZE: usize = OracleChange::SIZE + 8;
+}
+
+/// Settles Hit when `price <comparator> threshold` on a Pyth-compatible price account.
+/// `threshold` is in the feed's units (`price · 10^expo`); `expo` must match the feed's.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct FeedResolver {
+    pub feed: Pubkey,
+    pub comparator: Comparator,
+    pub threshold: i64,
+    pub expo: i32,
+    pub max_staleness_secs: i64,
+    /// Max confidence interval as bps of price
+    pub max_conf_bps: u16,
+}
+impl FeedResolver {
+    pub const SIZE: usize = 32 + 1 + 8 + 4 + 8 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum Comparator {
+    Above,
+    AtOrAbove,
+    Below,
+    AtOrBelow,
+}
+
+/// Aggregate price read from a feed account.
+struct FeedPrice {
+    price: i64,
+    conf: u64,
+    expo: i32,
+    publish_ts: i64,
+}
+
+/// One point of the b schedule.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct BPoint {
+    pub ts: i64,
+    pub b_fp: u64,
+}
+impl BPoint {
+    pub const SIZE: usize = 8 + 8;
+}
+
+/// Fee discount (bps of the fee) once rolling volume reaches `min_volume_fp`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeTier {
+    pub min_volume_fp: u64,
+    pub discount_bps: u16,
+}
+impl FeeTier {
+    pub const SIZE: usize = 8 + 2;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum FeeBucket {
+    Protocol,
+    Creator,
+}
+
+/// Where one trade's fee went.
+#[derive(Default)]
+struct RoutedFee {
+    referrer: Option<Pubkey>,
+    referral_fp: i128,
+    /// Protocol cut kept in the vault because no treasury is configured
+    protocol_retained_fp: i128,
+    creator_fp: i128,
+    lp_fp: i128,
+}
+
 /// ========== Params DTOs ==========
 
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct ConfigParams {
+    pub referral_fee_bps: u16,
+    pub fee_split: FeeSplit,
+    pub fee_tiers: [FeeTier; MAX_FEE_TIERS],
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct InitParams {
     pub b_fp: u64,
@@ -699,7 +2755,18 @@ pub struct InitParams {
     pub grace_period_secs: i64,
     pub max_trade_usdc_fp: u64,
     pub max_position_shares_fp: u64,
+    pub max_sell_shares_fp: u64,
+    pub max_sell_usdc_fp: u64,
     pub treasury: Option<Pubkey>,
+    pub fee_model: FeeModel,
+    /// None takes the Config default (or everything to the protocol without one). A custom
+    /// split needs the Config and at least its `protocol_bps`; it is fixed for the market's life
+    pub fee_split: Option<FeeSplit>,
+    /// Derive b from seeded liquidity (b = liquidity / ln 2)
+    pub auto_b: bool,
+    pub void_refund: VoidRefund,
+    /// Seconds after the settlement window opens before anyone may `force_void` (> 0)
+    pub settlement_timeout_secs: i64,
 }
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
@@ -710,8 +2777,20 @@ pub struct UpdateParams {
     pub grace_period_secs: Option<i64>,
     pub max_trade_usdc_fp: Option<u64>,
     pub max_position_shares_fp: Option<u64>,
+    pub max_sell_shares_fp: Option<u64>,
+    pub max_sell_usdc_fp: Option<u64>,
+    pub max_side_shares_fp: Option<u64>,
+    pub max_imbalance_fp: Option<u64>,
+    pub min_trade_interval_secs: Option<i64>,
+    pub max_trades_per_window: Option<u32>,
+    pub trade_window_secs: Option<i64>,
     pub treasury: Option<Pubkey>,
-    pub oracle_signer: Option<Pubkey>,
+    pub referral_fee_bps: Option<u16>,
+    pub residual_recipient: Option<Pubkey>,
+    pub auto_b: Option<bool>,
+    pub settlement_bond_fp: Option<u64>,
+    pub challenge_window_secs: Option<i64>,
+    pub settlement_timeout_secs: Option<i64>,
 }
 
 /// ========== Events ==========
@@ -733,20 +2812,181 @@ pub struct TradeEvent {
     pub usdc_fp: u64,
     pub shares_fp: u64,
     pub fee_fp: u64,
+    pub referrer: Option<Pubkey>,
+    pub referral_fee_fp: u64,
+    /// Volume tier applied (1-based; 0 = no discount)
+    pub fee_tier: u8,
+    pub p_hit_milli: i64,
+}
+
+#[event]
+pub struct Settled {
+    pub market: Pubkey,
+    pub outcome: Outcome,
+    pub hit_payout_fp: u64,
+    /// Empty / zeroed unless settled through `settle_market`
+    pub evidence_uri: String,
+    pub evidence_hash: [u8; 32],
+}
+
+#[event]
+pub struct Redeemed {
+    pub market: Pubkey,
+    pub user: Pubkey,
+    pub amount_fp: u64,
+}
+
+#[event]
+pub struct FeesWithdrawn {
+    pub market: Pubkey,
+    pub bucket: FeeBucket,
+    pub recipient: Pubkey,
+    pub amount_fp: u64,
+}
+
+#[event]
+pub struct LiquidityAdded {
+    pub market: Pubkey,
+    pub provider: Pubkey,
+    pub usdc_fp: u64,
+    pub lp_minted: u64,
+}
+
+#[event]
+pub struct LiquidityRemoved {
+    pub market: Pubkey,
+    pub provider: Pubkey,
+    pub lp_burned: u64,
+    pub usdc_fp: u64,
+}
+
+#[event]
+pub struct ResidualWithdrawn {
+    pub market: Pubkey,
+    pub recipient: Pubkey,
+    pub amount_fp: u64,
+    pub final_sweep: bool,
+}
+
+#[event]
+pub struct LiquiditySponsored {
+    pub market: Pubkey,
+    pub sponsor: Pubkey,
+    pub usdc_fp: u64,
+    pub total_sponsored_fp: u64,
+    pub b_fp: i64,
+}
+
+#[event]
+pub struct SponsorRefunded {
+    pub market: Pubkey,
+    pub sponsor: Pubkey,
+    pub amount_fp: u64,
+}
+
+#[event]
+pub struct LiquidityParamChanged {
+    pub market: Pubkey,
+    pub old_b_fp: i64,
+    pub new_b_fp: i64,
+    /// HIT price after the change; equal to the price before it
     pub p_hit_milli: i64,
 }
 
 #[event]
-pub struct Settled {
+pub struct BScheduleSet {
+    pub market: Pubkey,
+    pub points: Vec<BPoint>,
+}
+
+#[event]
+pub struct OracleVoted {
+    pub market: Pubkey,
+    pub oracle: Pubkey,
+    pub outcome: Outcome,
+    pub matching_votes: u8,
+    pub threshold: u8,
+}
+
+#[event]
+pub struct OracleVoteConflict {
+    pub market: Pubkey,
+    pub oracle: Pubkey,
+    pub outcome: Outcome,
+    pub conflicting_oracle: Pubkey,
+    pub conflicting_outcome: Outcome,
+}
+
+#[event]
+pub struct OutcomeProposed {
+    pub market: Pubkey,
+    pub proposer: Pubkey,
+    pub outcome: Outcome,
+    pub bond_fp: u64,
+    pub challenge_ends_ts: i64,
+}
+
+#[event]
+pub struct OutcomeDisputed {
+    pub market: Pubkey,
+    pub disputer: Pubkey,
+    pub proposed_outcome: Outcome,
+}
+
+#[event]
+pub struct BondClaimed {
+    pub market: Pubkey,
+    pub claimant: Pubkey,
+    pub amount_fp: u64,
+}
+
+#[event]
+pub struct EarlyResolutionStarted {
+    pub market: Pubkey,
+    pub outcome: Outcome,
+    pub settles_at_ts: i64,
+}
+
+#[event]
+pub struct EarlyResolutionCancelled {
+    pub market: Pubkey,
+    pub outcome: Outcome,
+}
+
+#[event]
+pub struct OracleUpdateQueued {
+    pub market: Pubkey,
+    pub change: OracleChange,
+    pub eta_ts: i64,
+}
+
+#[event]
+pub struct OracleUpdateApplied {
+    pub market: Pubkey,
+    pub change: OracleChange,
+}
+
+#[event]
+pub struct FeedResolverSet {
+    pub market: Pubkey,
+    pub resolver: Option<FeedResolver>,
+}
+
+#[event]
+pub struct FeedResolved {
     pub market: Pubkey,
+    pub feed: Pubkey,
+    pub price: i64,
+    pub conf: u64,
+    pub expo: i32,
+    pub publish_ts: i64,
     pub outcome: Outcome,
 }
 
 #[event]
-pub struct Redeemed {
+pub struct VolumeTrackingSet {
     pub market: Pubkey,
-    pub user: Pubkey,
-    pub amount_fp: u64,
+    pub tracked: bool,
 }
 
 #[event]
@@ -801,6 +3041,90 @@ pub enum AmmError {
     InsufficientPayment,
     #[msg("Invalid amount")]
     InvalidAmount,
+    #[msg("Sell exceeds max_sell_shares_fp or max_sell_usdc_fp")]
+    SellTooLarge,
+    #[msg("Market open interest exceeds max_side_shares_fp")]
+    OpenInterestTooLarge,
+    #[msg("Market imbalance exceeds max_imbalance_fp")]
+    ImbalanceTooLarge,
+    #[msg("Trade cooldown has not elapsed")]
+    TradeCooldown,
+    #[msg("Too many trades in the current window")]
+    TradeRateLimited,
+    #[msg("Amount exceeds accrued fees")]
+    InsufficientFees,
+    #[msg("Treasury USDC account required when a treasury is configured")]
+    TreasuryAccountMissing,
+    #[msg("Treasury USDC account does not match market treasury or mint")]
+    InvalidTreasury,
+    #[msg("Fee split must sum to 10_000 bps and keep the protocol's share")]
+    InvalidFeeSplit,
+    #[msg("Fee tiers must ascend by volume with discount <= 10_000 bps")]
+    InvalidFeeTiers,
+    #[msg("Already claimed")]
+    AlreadyClaimed,
+    #[msg("authority_usdc, vault_usdc and token_program are required to move subsidy")]
+    SubsidyAccountsMissing,
+    #[msg("Invalid b schedule")]
+    InvalidSchedule,
+    #[msg("b is driven by auto_b or a b schedule")]
+    BModeConflict,
+    #[msg("Vault does not cover b at the current price")]
+    BNotCovered,
+    #[msg("Market settles through the oracle quorum")]
+    QuorumRequired,
+    #[msg("Oracle quorum not configured")]
+    QuorumNotConfigured,
+    #[msg("Invalid oracle set or threshold")]
+    InvalidOracleSet,
+    #[msg("Oracle already voted")]
+    AlreadyVoted,
+    #[msg("Optimistic settlement not enabled")]
+    OptimisticNotEnabled,
+    #[msg("A settlement proposal already exists")]
+    ProposalExists,
+    #[msg("No settlement proposal")]
+    NoProposal,
+    #[msg("Proposal already disputed")]
+    AlreadyDisputed,
+    #[msg("Challenge window closed")]
+    ChallengeWindowClosed,
+    #[msg("Challenge window still open")]
+    ChallengeWindowOpen,
+    #[msg("Optimistic market: only a disputed proposal can be resolved directly")]
+    NotDisputed,
+    #[msg("Invalid payout fraction")]
+    InvalidPayoutFraction,
+    #[msg("Invalid oracle attestation")]
+    InvalidAttestation,
+    #[msg("Settlement timeout has not elapsed")]
+    SettlementTimeoutPending,
+    #[msg("Early resolution pending")]
+    EarlyResolutionPending,
+    #[msg("No early resolution pending")]
+    NoEarlyResolution,
+    #[msg("Early resolution delay has passed")]
+    EarlyResolutionFinal,
+    #[msg("Votes account required when an oracle quorum is configured")]
+    VotesAccountMissing,
+    #[msg("Settlement window already open")]
+    SettlementWindowOpen,
+    #[msg("No oracle update queued")]
+    NoPendingOracleUpdate,
+    #[msg("Oracle update notice period not elapsed")]
+    OracleUpdateNotReady,
+    #[msg("Oracle update queued")]
+    OracleUpdatePending,
+    #[msg("Evidence URI too long")]
+    EvidenceUriTooLong,
+    #[msg("Feed resolver not configured")]
+    FeedNotConfigured,
+    #[msg("Invalid or non-trading price feed")]
+    InvalidFeed,
+    #[msg("Feed price is stale")]
+    StaleFeed,
+    #[msg("Feed confidence interval too wide")]
+    FeedConfidenceTooWide,
 }
 
 /// ========== Math Helpers (LMSR) ==========
@@ -812,6 +3136,343 @@ fn position_side_shares(pos: &Position, side: Side) -> i128 {
     }
 }
 
+/// Track a trade's curve cost (negative for sale proceeds) in the position's cost basis,
+/// keeping the market aggregate equal to the sum of positive bases.
+fn add_cost_basis(m: &mut Market, pos: &mut Position, delta_fp: i128) -> Result<()> {
+    let old_fp = pos.net_cost_fp;
+    pos.net_cost_fp = old_fp.checked_add(delta_fp).ok_or(AmmError::MathOverflow)?;
+    m.cost_basis_outstanding_fp = m
+        .cost_basis_outstanding_fp
+        .checked_add(pos.net_cost_fp.max(0) - old_fp.max(0))
+        .ok_or(AmmError::MathOverflow)?;
+    Ok(())
+}
+
+/// Refund owed under a `Void` outcome for the given shares / cost basis.
+fn void_refund_fp(method: VoidRefund, hit_fp: i128, miss_fp: i128, cost_basis_fp: i128) -> Result<i128> {
+    match method {
+        VoidRefund::CostBasis => Ok(cost_basis_fp),
+        VoidRefund::FixedSplit { hit_fp: hit_price_fp } => {
+            split_payout_fp(hit_fp, miss_fp, hit_price_fp as i128)
+        }
+    }
+}
+
+/// Payout for `hit_fp`/`miss_fp` shares when each HIT share is worth `hit_price_fp` and
+/// each MISS share the rest of one unit (rounded down per side).
+fn split_payout_fp(hit_fp: i128, miss_fp: i128, hit_price_fp: i128) -> Result<i128> {
+    let hit_part = hit_fp.checked_mul(hit_price_fp).ok_or(AmmError::MathOverflow)? / FP_SCALER;
+    let miss_part = miss_fp
+        .checked_mul(FP_SCALER - hit_price_fp)
+        .ok_or(AmmError::MathOverflow)?
+        / FP_SCALER;
+    Ok(hit_part.checked_add(miss_part).ok_or(AmmError::MathOverflow)?)
+}
+
+/// Share of the full Void refunds the vault can pay: `available / owed`, capped at one.
+fn void_payout_ratio_fp(available_fp: i128, owed_fp: i128) -> Result<i128> {
+    if owed_fp <= 0 || available_fp >= owed_fp {
+        return Ok(FP_SCALER);
+    }
+    available_fp
+        .max(0)
+        .checked_mul(FP_SCALER)
+        .and_then(|v| v.checked_div(owed_fp))
+        .ok_or(error!(AmmError::MathOverflow))
+}
+
+/// A Void refund after the market's haircut (rounded down).
+fn scale_void_refund_fp(refund_fp: i128, ratio_fp: i128) -> Result<i128> {
+    refund_fp
+        .checked_mul(ratio_fp)
+        .map(|v| v / FP_SCALER)
+        .ok_or(error!(AmmError::MathOverflow))
+}
+
+/// Reject fee model parameters that could exceed 100% or never ramp.
+fn validate_fee_model(model: FeeModel, fee_bps: u16) -> Result<()> {
+    if let FeeModel::DeadlineRamp { max_fee_bps, ramp_secs } = model {
+        require!(max_fee_bps <= 10_000 && max_fee_bps >= fee_bps, AmmError::InvalidFee);
+        require!(ramp_secs > 0, AmmError::InvalidFee);
+    }
+    Ok(())
+}
+
+/// Effective fee in bps for a trade executed now, per the market's fee model, less the
+/// trader's volume discount (bps of the fee).
+fn trade_fee_bps(m: &Market, now: i64, discount_bps: u16) -> Result<u16> {
+    let bps = match m.fee_model {
+        FeeModel::Flat => m.fee_bps,
+        FeeModel::ProbabilityScaled => {
+            let p = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
+            let scale = (4.0 * p * (1.0 - p)).clamp(0.0, 1.0);
+            ((m.fee_bps as f64) * scale).round() as u16
+        }
+        FeeModel::DeadlineRamp { max_fee_bps, ramp_secs } => {
+            let left = m.deadline_ts.saturating_sub(now).max(0);
+            if left >= ramp_secs {
+                m.fee_bps
+            } else {
+                // i128 so a very long ramp cannot overflow; extra never exceeds span
+                let span = max_fee_bps.saturating_sub(m.fee_bps) as i128;
+                let extra = span
+                    .checked_mul((ramp_secs - left) as i128)
+                    .ok_or(AmmError::MathOverflow)?
+                    / ramp_secs as i128;
+                m.fee_bps
+                    .checked_add(extra as u16)
+                    .ok_or(AmmError::MathOverflow)?
+            }
+        }
+    };
+    let discounted = (bps as u32) * (10_000 - discount_bps.min(10_000) as u32) / 10_000;
+    Ok(discounted as u16)
+}
+
+/// Highest configured tier reached by the trader's rolling volume: (1-based tier, discount).
+fn volume_fee_tier(config: Option<&Config>, volume: Option<&TraderVolume>, now: i64) -> (u8, u16) {
+    let (Some(config), Some(volume)) = (config, volume) else {
+        return (0, 0);
+    };
+    let rolling_fp = volume.rolling_volume_fp(now);
+    config
+        .fee_tiers
+        .iter()
+        .enumerate()
+        .rev()
+        .find(|(_, t)| t.discount_bps > 0 && rolling_fp >= t.min_volume_fp)
+        .map(|(i, t)| (i as u8 + 1, t.discount_bps))
+        .unwrap_or((0, 0))
+}
+
+/// Active tiers must ascend by threshold and discount at most 100%.
+fn validate_fee_tiers(tiers: &[FeeTier; MAX_FEE_TIERS]) -> Result<()> {
+    let mut prev_min = None;
+    for t in tiers.iter().filter(|t| t.discount_bps > 0) {
+        require!(t.discount_bps <= 10_000, AmmError::InvalidFeeTiers);
+        if let Some(prev) = prev_min {
+            require!(t.min_volume_fp > prev, AmmError::InvalidFeeTiers);
+        }
+        prev_min = Some(t.min_volume_fp);
+    }
+    Ok(())
+}
+
+/// Fee on a trade of `dcost_fp` at `fee_bps`.
+fn fee_from_bps(dcost_fp: i128, fee_bps: u16) -> Result<i128> {
+    Ok(dcost_fp
+        .checked_mul(fee_bps as i128)
+        .ok_or(AmmError::MathOverflow)?
+        / 10_000i128)
+}
+
+/// Referral share of the fee in bps: market override, else protocol config, else 0.
+fn referral_fee_bps(m: &Market, config: Option<&Config>) -> u16 {
+    m.referral_fee_bps
+        .or_else(|| config.map(|c| c.referral_fee_bps))
+        .unwrap_or(0)
+}
+
+/// Enforce per-wallet cooldown / rate limit and stamp the trade on the position.
+fn record_trade(
+    pos: &mut Position,
+    now: i64,
+    min_interval_secs: i64,
+    max_trades_per_window: u32,
+    window_secs: i64,
+) -> Result<()> {
+    if min_interval_secs > 0 && pos.last_trade_ts > 0 {
+        require!(
+            now >= pos.last_trade_ts.saturating_add(min_interval_secs),
+            AmmError::TradeCooldown
+        );
+    }
+    if max_trades_per_window > 0 {
+        if now >= pos.window_start_ts.saturating_add(window_secs) {
+            pos.window_start_ts = now;
+            pos.window_trades = 0;
+        }
+        require!(pos.window_trades < max_trades_per_window, AmmError::TradeRateLimited);
+        pos.window_trades += 1;
+    }
+    pos.last_trade_ts = now;
+    Ok(())
+}
+
+/// Largest b whose worst-case loss (b·ln 2) the subsidy covers, capped to the valid range.
+fn max_b_for_liquidity(liquidity_usdc_fp: i128) -> i128 {
+    let b = (liquidity_usdc_fp as f64) / core::f64::consts::LN_2;
+    (b as i128).min(MAX_B_FP)
+}
+
+/// b increase that `amount_fp` of new subsidy pays for at the current price: raising b by
+/// Δb adds up to Δb·ln(1/min(p, 1−p)) to the worst-case loss of either outcome.
+fn b_raise_for_deposit(m: &Market, amount_fp: i128) -> Result<i128> {
+    let p_hit = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
+    let worst_ln = -p_hit.min(1.0 - p_hit).ln();
+    Ok(((amount_fp as f64) / worst_ln) as i128)
+}
+
+/// Subsidy that covers the LMSR worst-case loss for `b_fp` from a flat curve (b·ln 2,
+/// rounded up).
+fn subsidy_for_b(b_fp: i128) -> i128 {
+    ((b_fp as f64) * core::f64::consts::LN_2).ceil() as i128
+}
+
+/// Vault balance left once every outcome is covered from the current curve state. If side
+/// i wins, the market owes its outstanding shares plus whatever further buying of i can
+/// still cost it, C(q) − q_i = b·ln(1/p_i); fee buckets and escrowed bonds are held aside.
+/// Negative when b is not fully funded at the current price.
+fn solvency_margin_fp(m: &Market, vault_amount: u64) -> Result<i128> {
+    let cost_fp = lmsr_cost(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
+    let side_need = |owed_fp: i128, q_fp: i128| -> Result<i128> {
+        owed_fp
+            .checked_add(cost_fp - q_fp)
+            .ok_or(error!(AmmError::MathOverflow))
+    };
+    let need_fp = side_need(m.hit_shares_outstanding_fp, m.q_hit_fp)?
+        .max(side_need(m.miss_shares_outstanding_fp, m.q_miss_fp)?)
+        .max(m.cost_basis_outstanding_fp);
+    (vault_amount as i128)
+        .checked_sub(m.fee_and_bond_reserve_fp()?)
+        .and_then(|v| v.checked_sub(need_fp))
+        .ok_or(error!(AmmError::MathOverflow))
+}
+
+/// Largest b the vault covers at the current price: for each side, the vault left after
+/// its outstanding shares divided by ln(1/p_i) (see `solvency_margin_fp`).
+fn max_covered_b_fp(m: &Market, vault_amount: u64) -> Result<i128> {
+    let p_hit = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
+    let avail_fp = (vault_amount as i128)
+        .checked_sub(m.fee_and_bond_reserve_fp()?)
+        .ok_or(AmmError::MathOverflow)?;
+    if avail_fp < m.cost_basis_outstanding_fp {
+        return Ok(0);
+    }
+    let side_b = |owed_fp: i128, p: f64| ((avail_fp - owed_fp).max(0) as f64 / -p.ln()) as i128;
+    Ok(side_b(m.hit_shares_outstanding_fp, p_hit)
+        .min(side_b(m.miss_shares_outstanding_fp, 1.0 - p_hit))
+        .min(MAX_B_FP))
+}
+
+/// USDC to settle with the authority once `m` has been rebased from `old_b_fp`: a raise
+/// pulls in exactly what keeps every outcome covered at the new b and current price (see
+/// `solvency_margin_fp`); a cut releases its b·ln 2 subsidy difference from the authority's
+/// own capital, but never more than the vault can spare at the new b. Positive = top-up,
+/// negative = release.
+fn b_change_subsidy_fp(m: &Market, old_b_fp: i128, vault_amount: u64) -> Result<i128> {
+    let margin_fp = solvency_margin_fp(m, vault_amount)?;
+    if m.b_fp > old_b_fp {
+        return Ok((-margin_fp).max(0));
+    }
+    let freed_fp = subsidy_for_b(old_b_fp) - subsidy_for_b(m.b_fp);
+    let authority_capital = m.liquidity_usdc_fp - m.lp_liquidity_fp - m.sponsor_liquidity_fp;
+    require!(freed_fp <= authority_capital, AmmError::InsufficientBalance);
+    Ok(-(freed_fp.min(margin_fp).max(0)))
+}
+
+/// Change b while keeping p_hit fixed: price depends only on (q_hit − q_miss)/b, so scale
+/// the difference by new_b/old_b, then shift both sides by a common constant (which moves
+/// neither prices nor trade costs) so each curve q stays at or above the shares holders
+/// actually own and can sell back. Outstanding shares (what redemption owes) are untouched.
+fn rescale_curve(m: &mut Market, new_b_fp: i128) -> Result<()> {
+    require!((MIN_B_FP..=MAX_B_FP).contains(&new_b_fp), AmmError::InvalidB);
+    let diff_fp = m
+        .q_hit_fp
+        .checked_sub(m.q_miss_fp)
+        .and_then(|d| d.checked_mul(new_b_fp))
+        .ok_or(AmmError::MathOverflow)?
+        / m.b_fp;
+    let q_miss_fp = m
+        .miss_shares_outstanding_fp
+        .max(m.hit_shares_outstanding_fp.checked_sub(diff_fp).ok_or(AmmError::MathOverflow)?);
+    m.q_hit_fp = q_miss_fp.checked_add(diff_fp).ok_or(AmmError::MathOverflow)?;
+    m.q_miss_fp = q_miss_fp;
+    m.b_fp = new_b_fp;
+    Ok(())
+}
+
+/// In auto-b mode, move b to what the subsidy covers on a flat curve (liquidity / ln 2),
+/// keeping the price fixed, and emit the change. Once trading has moved the price that
+/// overstates the cover, so b is never raised past `max_covered_b_fp`. No-op while the
+/// subsidy is below the minimum b.
+fn apply_auto_b(m: &mut Account<Market>, vault_amount: u64) -> Result<()> {
+    if !m.auto_b {
+        return Ok(());
+    }
+    let mut new_b_fp = max_b_for_liquidity(m.liquidity_usdc_fp);
+    if new_b_fp > m.b_fp {
+        new_b_fp = new_b_fp.min(max_covered_b_fp(m, vault_amount)?.max(m.b_fp));
+    }
+    if new_b_fp < MIN_B_FP || new_b_fp == m.b_fp {
+        return Ok(());
+    }
+    rebase_b(m, new_b_fp)
+}
+
+/// b at `now` on a piecewise-linear schedule: flat before the first and after the last
+/// point, linearly interpolated in between.
+fn scheduled_b(schedule: &[BPoint], now: i64) -> Option<i128> {
+    let first = schedule.first()?;
+    if now <= first.ts {
+        return Some(first.b_fp as i128);
+    }
+    for w in schedule.windows(2) {
+        let (a, b) = (w[0], w[1]);
+        if now < b.ts {
+            let span = (b.ts - a.ts) as i128;
+            let elapsed = (now - a.ts) as i128;
+            let db = b.b_fp as i128 - a.b_fp as i128;
+            return Some(a.b_fp as i128 + db * elapsed / span);
+        }
+    }
+    schedule.last().map(|p| p.b_fp as i128)
+}
+
+/// Move b onto the schedule at `now`, rescaling q so the price stays continuous. A raise
+/// must leave every outcome covered by the vault at the current price
+/// (`solvency_margin_fp`), otherwise the trade fails until the market is topped up or the
+/// schedule changed.
+fn apply_b_schedule(m: &mut Account<Market>, now: i64, vault_amount: u64) -> Result<()> {
+    let Some(new_b_fp) = scheduled_b(&m.b_schedule, now) else {
+        return Ok(());
+    };
+    if new_b_fp == m.b_fp {
+        return Ok(());
+    }
+    let raise = new_b_fp > m.b_fp;
+    rebase_b(m, new_b_fp)?;
+    if raise {
+        require!(solvency_margin_fp(m, vault_amount)? >= 0, AmmError::BNotCovered);
+    }
+    Ok(())
+}
+
+/// Reject a hand-set b change on a market whose b follows `auto_b` or a schedule.
+fn require_manual_b(m: &Market, auto_b: bool) -> Result<()> {
+    require!(!auto_b && m.b_schedule.is_empty(), AmmError::BModeConflict);
+    Ok(())
+}
+
+/// Price-preserving b change on a market account, reported as `LiquidityParamChanged`.
+fn rebase_b(m: &mut Account<Market>, new_b_fp: i128) -> Result<()> {
+    let old_b_fp = m.b_fp;
+    rescale_curve(m, new_b_fp)?;
+    emit!(LiquidityParamChanged {
+        market: m.key(),
+        old_b_fp: old_b_fp as i64,
+        new_b_fp: m.b_fp as i64,
+        p_hit_milli: price_milli(m)?,
+    });
+    Ok(())
+}
+
+/// Current HIT price in milli-units, as reported in events.
+fn price_milli(m: &Market) -> Result<i64> {
+    let p_hit = lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp)?;
+    Ok((p_hit * (PRICE_MILLI_SCALER as f64)) as i64)
+}
+
 /// LMSR price of HIT given b, q_hit, q_miss (all in fp)
 fn lmsr_price_hit(b_fp: i128, q_hit_fp: i128, q_miss_fp: i128) -> Result<f64> {
     let b = (b_fp as f64) / (FP_SCALER as f64);
@@ -920,6 +3581,375 @@ fn solve_delta_q_bisect(
 
 /// ========== Utilities ==========
 
+/// With optimistic settlement enabled, direct paths (`settle_market`, quorum votes) may
+/// only resolve a disputed proposal once the settlement window is open. Before it no
+/// proposal can exist, so early resolution stays available.
+fn require_escalated(m: &Market, window_open: bool) -> Result<()> {
+    if m.proposal.is_some() || (window_open && m.settlement_bond_fp > 0) {
+        require!(
+            m.proposal.is_some_and(|p| p.disputer.is_some()),
+            AmmError::NotDisputed
+        );
+    }
+    Ok(())
+}
+
+/// M-of-N with at most `MAX_ORACLES` distinct keys and M a strict majority, so no minority of
+/// the set can settle; an empty set with M = 0 disables the quorum.
+fn validate_oracle_quorum(oracles: &[Pubkey], threshold: u8) -> Result<()> {
+    require!(oracles.len() <= MAX_ORACLES, AmmError::InvalidOracleSet);
+    require!((threshold as usize) <= oracles.len(), AmmError::InvalidOracleSet);
+    require!(oracles.is_empty() == (threshold == 0), AmmError::InvalidOracleSet);
+    require!(oracles.is_empty() || 2 * threshold as usize > oracles.len(), AmmError::InvalidOracleSet);
+    for (i, o) in oracles.iter().enumerate() {
+        require!(!oracles[..i].contains(o), AmmError::InvalidOracleSet);
+    }
+    Ok(())
+}
+
+/// Queue `change` behind `ORACLE_UPDATE_NOTICE_SECS`, replacing any queued change. It must be
+/// applicable before the deadline, which stays frozen until it is applied.
+fn queue_oracle_change(m: &mut Account<Market>, change: OracleChange) -> Result<()> {
+    require!(m.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+    let eta_ts = Clock::get()?.unix_timestamp + ORACLE_UPDATE_NOTICE_SECS;
+    require!(eta_ts < m.deadline_ts, AmmError::AfterDeadline);
+
+    m.pending_oracle_update = Some(OracleUpdate {
+        change: change.clone(),
+        eta_ts,
+    });
+    emit!(OracleUpdateQueued {
+        market: m.key(),
+        change,
+        eta_ts,
+    });
+    Ok(())
+}
+
+/// Parse the aggregate price from a Pyth v2 price account, requiring the magic number and
+/// a trading status.
+fn read_pyth_price(data: &[u8]) -> Result<FeedPrice> {
+    let bytes = |off: usize, len: usize| data.get(off..off + len).ok_or(AmmError::InvalidFeed);
+    let u32_at = |off: usize| -> Result<u32> { Ok(u32::from_le_bytes(bytes(off, 4)?.try_into().unwrap())) };
+    let i64_at = |off: usize| -> Result<i64> { Ok(i64::from_le_bytes(bytes(off, 8)?.try_into().unwrap())) };
+
+    require!(u32_at(0)? == PYTH_MAGIC, AmmError::InvalidFeed);
+    require!(u32_at(PYTH_AGG_STATUS_OFFSET)? == PYTH_STATUS_TRADING, AmmError::InvalidFeed);
+    Ok(FeedPrice {
+        price: i64_at(PYTH_AGG_PRICE_OFFSET)?,
+        conf: i64_at(PYTH_AGG_CONF_OFFSET)? as u64,
+        expo: u32_at(PYTH_EXPO_OFFSET)? as i32,
+        publish_ts: i64_at(PYTH_TIMESTAMP_OFFSET)?,
+    })
+}
+
+/// Hit/Miss from a feed reading under `resolver`, after the expo, staleness (published
+/// after the deadline, at most `max_staleness_secs` old) and confidence checks.
+fn feed_outcome(resolver: &FeedResolver, feed: &FeedPrice, deadline_ts: i64) -> Result<Outcome> {
+    require!(feed.expo == resolver.expo, AmmError::InvalidFeed);
+    // The price at the deadline, not whichever later price a caller waits for
+    require!(
+        feed.publish_ts >= deadline_ts && feed.publish_ts - deadline_ts <= resolver.max_staleness_secs,
+        AmmError::StaleFeed
+    );
+    require!(
+        (feed.conf as u128) * 10_000
+            <= (feed.price.unsigned_abs() as u128) * resolver.max_conf_bps as u128,
+        AmmError::FeedConfidenceTooWide
+    );
+
+    let hit = match resolver.comparator {
+        Comparator::Above => feed.price > resolver.threshold,
+        Comparator::AtOrAbove => feed.price >= resolver.threshold,
+        Comparator::Below => feed.price < resolver.threshold,
+        Comparator::AtOrBelow => feed.price <= resolver.threshold,
+    };
+    Ok(if hit { Outcome::Hit } else { Outcome::Miss })
+}
+
+/// Bytes the oracle signs to attest a settlement (see `OracleAttestation`).
+fn attestation_message(
+    market: &Pubkey,
+    outcome: Outcome,
+    hit_payout_fp: u64,
+    att: &OracleAttestation,
+    evidence_uri: &str,
+    evidence_hash: &[u8; 32],
+) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(32 + 1 + 8 + 8 + 8 + 32 + 32);
+    msg.extend_from_slice(market.as_ref());
+    msg.push(outcome as u8);
+    msg.extend_from_slice(&hit_payout_fp.to_le_bytes());
+    msg.extend_from_slice(&att.timestamp.to_le_bytes());
+    msg.extend_from_slice(&att.nonce.to_le_bytes());
+    msg.extend_from_slice(evidence_hash);
+    msg.extend_from_slice(hash(evidence_uri.as_bytes()).as_ref());
+    msg
+}
+
+/// Require the instruction just before this one to be a native Ed25519 program check of a
+/// single signature by `signer` over `message`, with all data inline in that instruction.
+fn verify_ed25519_attestation(ix_ai: &AccountInfo, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    let current = ix_sysvar::load_current_index_checked(ix_ai)?;
+    require!(current > 0, AmmError::InvalidAttestation);
+    let ix = ix_sysvar::load_instruction_at_checked(current as usize - 1, ix_ai)?;
+    check_ed25519_ix(&ix, signer, message)
+}
+
+/// Check a loaded Ed25519 program instruction (see `verify_ed25519_attestation`).
+fn check_ed25519_ix(ix: &Instruction, signer: &Pubkey, message: &[u8]) -> Result<()> {
+    require!(ix.program_id == ed25519_program::ID, AmmError::InvalidAttestation);
+
+    // [num_signatures: u8, padding: u8, then 7 u16 offsets: signature, signature ix,
+    //  pubkey, pubkey ix, message, message size, message ix]
+    let d = &ix.data;
+    require!(d.len() >= 16 && d[0] == 1, AmmError::InvalidAttestation);
+    let offset = |i: usize| u16::from_le_bytes([d[2 + 2 * i], d[3 + 2 * i]]);
+    let inline = u16::MAX;
+    require!(
+        offset(1) == inline && offset(3) == inline && offset(6) == inline,
+        AmmError::InvalidAttestation
+    );
+    let (pk_off, msg_off, msg_len) = (offset(2) as usize, offset(4) as usize, offset(5) as usize);
+    let pk = d.get(pk_off..pk_off + 32).ok_or(AmmError::InvalidAttestation)?;
+    let msg = d.get(msg_off..msg_off + msg_len).ok_or(AmmError::InvalidAttestation)?;
+    require!(pk == signer.as_ref() && msg == message, AmmError::InvalidAttestation);
+    Ok(())
+}
+
+/// Halt trading on a known outcome and start the mandatory delay before it settles.
+fn begin_early_resolution(m: &mut Account<Market>, outcome: Outcome, now: i64) {
+    m.early_resolution = true;
+    m.early_resolution_ts = now;
+    m.early_outcome = outcome;
+    emit!(EarlyResolutionStarted {
+        market: m.key(),
+        outcome,
+        settles_at_ts: now + EARLY_RESOLUTION_DELAY_SECS,
+    });
+}
+
+/// Finalize the market outcome without settlement evidence.
+fn apply_settlement(m: &mut Account<Market>, outcome: Outcome) -> Result<()> {
+    apply_settlement_with_evidence(m, outcome, String::new(), [0u8; 32])
+}
+
+/// Finalize the market outcome: halt trading and emit `Settled`.
+fn apply_settlement_with_evidence(
+    m: &mut Account<Market>,
+    outcome: Outcome,
+    evidence_uri: String,
+    evidence_hash: [u8; 32],
+) -> Result<()> {
+    match outcome {
+        Outcome::Hit => m.hit_payout_fp = FP_SCALER,
+        Outcome::Miss | Outcome::Void => m.hit_payout_fp = 0,
+        _ => {}
+    }
+    m.outcome = outcome;
+    m.paused = true;
+    emit!(Settled {
+        market: m.key(),
+        outcome,
+        hit_payout_fp: m.hit_payout_fp as u64,
+        evidence_uri,
+        evidence_hash,
+    });
+    Ok(())
+}
+
+/// Move b to `new_b_fp` without moving the price (curve q is rescaled) and settle the
+/// subsidy with the authority per `b_change_subsidy_fp`: a top-up is pulled from
+/// `authority_usdc`, a release is paid back to it. Before the deadline only.
+fn update_b_preserving_price(accts: &mut AdminUpdate<'_>, new_b_fp: i128) -> Result<()> {
+    require!(accts.market.outcome == Outcome::Unresolved, AmmError::AlreadySettled);
+    require!(
+        Clock::get()?.unix_timestamp < accts.market.deadline_ts,
+        AmmError::AfterDeadline
+    );
+    let old_b_fp = accts.market.b_fp;
+    if new_b_fp == old_b_fp {
+        return Ok(());
+    }
+    let vault_usdc = accts.vault_usdc.as_ref().ok_or(AmmError::SubsidyAccountsMissing)?;
+    rebase_b(&mut accts.market, new_b_fp)?;
+    let delta_fp = b_change_subsidy_fp(&accts.market, old_b_fp, vault_usdc.amount)?;
+
+    if delta_fp != 0 {
+        let (Some(authority_usdc), Some(token_program)) =
+            (accts.authority_usdc.as_ref(), accts.token_program.as_ref())
+        else {
+            return err!(AmmError::SubsidyAccountsMissing);
+        };
+        if delta_fp > 0 {
+            token::transfer(
+                CpiContext::new(
+                    token_program.to_account_info(),
+                    Transfer {
+                        from: authority_usdc.to_account_info(),
+                        to: vault_usdc.to_account_info(),
+                        authority: accts.authority.to_account_info(),
+                    },
+                ),
+                delta_fp as u64,
+            )?;
+        } else {
+            let m = &accts.market;
+            let seeds = [
+                SEED_MARKET,
+                m.authority.as_ref(),
+                m.milestone_id.as_ref(),
+                core::slice::from_ref(&m.bump),
+            ];
+            vault_transfer(
+                token_program,
+                vault_usdc,
+                authority_usdc.to_account_info(),
+                m.to_account_info(),
+                &seeds,
+                (-delta_fp) as u64,
+            )?;
+        }
+    }
+
+    let m = &mut accts.market;
+    m.liquidity_usdc_fp = m.liquidity_usdc_fp.checked_add(delta_fp).ok_or(AmmError::MathOverflow)?;
+    Ok(())
+}
+
+/// Transfer `amount` out of the vault, signed by the market PDA.
+fn vault_transfer<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    market: AccountInfo<'info>,
+    seeds: &[&[u8]],
+    amount: u64,
+) -> Result<()> {
+    token::transfer(
+        CpiContext::new_with_signer(
+            token_program.to_account_info(),
+            Transfer {
+                from: vault.to_account_info(),
+                to,
+                authority: market,
+            },
+            &[seeds],
+        ),
+        amount,
+    )
+}
+
+/// Pay the referrer cut of `fee_fp` (if a referrer is present), then split the rest per
+/// `split`: the protocol cut goes to the treasury (if configured, else it is retained), the
+/// creator and LP cuts stay in the vault. Returns the amounts for bucket accounting.
+/// Referrers are not authenticated: a trader naming a wallet it controls just takes the
+/// referral cut back as a rebate.
+#[allow(clippy::too_many_arguments)]
+fn route_trade_fee<'info>(
+    token_program: &Program<'info, Token>,
+    vault: &Account<'info, TokenAccount>,
+    market: AccountInfo<'info>,
+    treasury_usdc: Option<&Account<'info, TokenAccount>>,
+    referrer_usdc: Option<&Account<'info, TokenAccount>>,
+    seeds: &[&[u8]],
+    fee_fp: i128,
+    referral_bps: u16,
+    split: FeeSplit,
+    treasury: Option<Pubkey>,
+    usdc_mint: Pubkey,
+) -> Result<RoutedFee> {
+    let (mut routed, protocol_fp) = split_fee(fee_fp, referral_bps, referrer_usdc.map(|r| r.owner), split)?;
+    if let Some(r) = referrer_usdc {
+        if routed.referral_fp > 0 {
+            vault_transfer(
+                token_program,
+                vault,
+                r.to_account_info(),
+                market.clone(),
+                seeds,
+                routed.referral_fp as u64,
+            )?;
+        }
+    }
+
+    let Some(treasury) = treasury else {
+        routed.protocol_retained_fp = protocol_fp;
+        return Ok(routed);
+    };
+    if protocol_fp > 0 {
+        let treasury_usdc = treasury_usdc.ok_or(AmmError::TreasuryAccountMissing)?;
+        require!(
+            treasury_usdc.owner == treasury && treasury_usdc.mint == usdc_mint,
+            AmmError::InvalidTreasury
+        );
+        vault_transfer(
+            token_program,
+            vault,
+            treasury_usdc.to_account_info(),
+            market,
+            seeds,
+            protocol_fp as u64,
+        )?;
+    }
+    Ok(routed)
+}
+
+/// Divide a trade fee: the referrer's cut first, then the rest by `split`, with LPs taking
+/// the rounding remainder. Returns the routing (protocol cut not yet placed) and the protocol cut.
+fn split_fee(
+    fee_fp: i128,
+    referral_bps: u16,
+    referrer: Option<Pubkey>,
+    split: FeeSplit,
+) -> Result<(RoutedFee, i128)> {
+    let mut routed = RoutedFee {
+        referrer,
+        ..RoutedFee::default()
+    };
+    if referrer.is_some() {
+        routed.referral_fp = fee_from_bps(fee_fp, referral_bps)?;
+    }
+    let rest_fp = fee_fp.checked_sub(routed.referral_fp).ok_or(AmmError::MathOverflow)?.max(0);
+    let protocol_fp = fee_from_bps(rest_fp, split.protocol_bps)?;
+    routed.creator_fp = fee_from_bps(rest_fp, split.creator_bps)?;
+    routed.lp_fp = rest_fp
+        .checked_sub(protocol_fp)
+        .and_then(|v| v.checked_sub(routed.creator_fp))
+        .ok_or(AmmError::MathOverflow)?;
+    Ok((routed, protocol_fp))
+}
+
+/// Validate that a fee split covers exactly 100% of the fee.
+fn validate_fee_split(split: FeeSplit) -> Result<()> {
+    let total = split.protocol_bps as u32 + split.creator_bps as u32 + split.lp_bps as u32;
+    require!(total == 10_000, AmmError::InvalidFeeSplit);
+    Ok(())
+}
+
+impl<'info> PostBond<'info> {
+    /// Move a settlement bond from the bonder into the vault escrow.
+    fn post_bond(&mut self, bond_fp: u64) -> Result<()> {
+        token::transfer(
+            CpiContext::new(
+                self.token_program.to_account_info(),
+                Transfer {
+                    from: self.bonder_usdc.to_account_info(),
+                    to: self.vault_usdc.to_account_info(),
+                    authority: self.bonder.to_account_info(),
+                },
+            ),
+            bond_fp,
+        )?;
+        self.market.bond_escrow_fp = self
+            .market
+            .bond_escrow_fp
+            .checked_add(bond_fp as i128)
+            .ok_or(AmmError::MathOverflow)?;
+        Ok(())
+    }
+}
+
 impl<'info> Trade<'info> {
     /// Example of signer seeds builder (now lifetime-safe)
     fn _signer_seeds(&self) -> [&[u8]; 4] {
@@ -934,3 +3964,469 @@ impl<'info> Trade<'info> {
         ]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INLINE: u16 = u16::MAX;
+    const PK_OFF: u16 = 16;
+    const SIG_OFF: u16 = PK_OFF + 32;
+    const MSG_OFF: u16 = SIG_OFF + 64;
+
+    /// Ed25519 program instruction with one signature; `ix_indices` are the signature,
+    /// pubkey and message instruction indices.
+    fn ed25519_ix(signer: &Pubkey, message: &[u8], ix_indices: [u16; 3]) -> Instruction {
+        let offsets = [
+            SIG_OFF,
+            ix_indices[0],
+            PK_OFF,
+            ix_indices[1],
+            MSG_OFF,
+            message.len() as u16,
+            ix_indices[2],
+        ];
+        let mut data = vec![1u8, 0];
+        for o in offsets {
+            data.extend_from_slice(&o.to_le_bytes());
+        }
+        data.extend_from_slice(signer.as_ref());
+        data.extend_from_slice(&[7u8; 64]);
+        data.extend_from_slice(message);
+        Instruction { program_id: ed25519_program::ID, accounts: vec![], data }
+    }
+
+    fn message() -> Vec<u8> {
+        let att = OracleAttestation { timestamp: 1_700_000_000, nonce: 1 };
+        attestation_message(&Pubkey::new_unique(), Outcome::Hit, 0, &att, "ipfs://evidence", &[7; 32])
+    }
+
+    #[test]
+    fn ed25519_inline_signature_is_accepted() {
+        let (signer, msg) = (Pubkey::new_unique(), message());
+        let ix = ed25519_ix(&signer, &msg, [INLINE; 3]);
+        assert!(check_ed25519_ix(&ix, &signer, &msg).is_ok());
+    }
+
+    #[test]
+    fn attestation_covers_the_evidence() {
+        let (signer, market) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let att = OracleAttestation { timestamp: 1_700_000_000, nonce: 1 };
+        let signed = attestation_message(&market, Outcome::Hit, 0, &att, "ipfs://evidence", &[7; 32]);
+        let ix = ed25519_ix(&signer, &signed, [INLINE; 3]);
+        assert!(check_ed25519_ix(&ix, &signer, &signed).is_ok());
+        for swapped in [
+            attestation_message(&market, Outcome::Hit, 0, &att, "ipfs://other", &[7; 32]),
+            attestation_message(&market, Outcome::Hit, 0, &att, "ipfs://evidence", &[8; 32]),
+        ] {
+            assert!(check_ed25519_ix(&ix, &signer, &swapped).is_err());
+        }
+    }
+
+    #[test]
+    fn ed25519_non_inline_offsets_are_rejected() {
+        let (signer, msg) = (Pubkey::new_unique(), message());
+        for indices in [[0, INLINE, INLINE], [INLINE, 0, INLINE], [INLINE, INLINE, 0]] {
+            let ix = ed25519_ix(&signer, &msg, indices);
+            assert_eq!(
+                check_ed25519_ix(&ix, &signer, &msg),
+                Err(AmmError::InvalidAttestation.into())
+            );
+        }
+    }
+
+    #[test]
+    fn ed25519_wrong_program_signer_or_message_is_rejected() {
+        let (signer, msg) = (Pubkey::new_unique(), message());
+        let mut ix = ed25519_ix(&signer, &msg, [INLINE; 3]);
+        assert!(check_ed25519_ix(&ix, &Pubkey::new_unique(), &msg).is_err());
+        assert!(check_ed25519_ix(&ix, &signer, &msg[1..]).is_err());
+        ix.program_id = Pubkey::new_unique();
+        assert!(check_ed25519_ix(&ix, &signer, &msg).is_err());
+    }
+
+    #[test]
+    fn ed25519_truncated_data_is_rejected() {
+        let (signer, msg) = (Pubkey::new_unique(), message());
+        let mut ix = ed25519_ix(&signer, &msg, [INLINE; 3]);
+        ix.data.truncate(MSG_OFF as usize + 4);
+        assert!(check_ed25519_ix(&ix, &signer, &msg).is_err());
+        ix.data.truncate(10);
+        assert!(check_ed25519_ix(&ix, &signer, &msg).is_err());
+    }
+
+    const DEADLINE: i64 = 1_700_000_000;
+
+    /// Mock Pyth v2 price account with the given aggregate fields.
+    fn pyth_buffer(magic: u32, expo: i32, ts: i64, price: i64, conf: u64, status: u32) -> Vec<u8> {
+        let mut data = vec![0u8; 240];
+        data[0..4].copy_from_slice(&magic.to_le_bytes());
+        data[PYTH_EXPO_OFFSET..PYTH_EXPO_OFFSET + 4].copy_from_slice(&expo.to_le_bytes());
+        data[PYTH_TIMESTAMP_OFFSET..PYTH_TIMESTAMP_OFFSET + 8].copy_from_slice(&ts.to_le_bytes());
+        data[PYTH_AGG_PRICE_OFFSET..PYTH_AGG_PRICE_OFFSET + 8].copy_from_slice(&price.to_le_bytes());
+        data[PYTH_AGG_CONF_OFFSET..PYTH_AGG_CONF_OFFSET + 8].copy_from_slice(&conf.to_le_bytes());
+        data[PYTH_AGG_STATUS_OFFSET..PYTH_AGG_STATUS_OFFSET + 4].copy_from_slice(&status.to_le_bytes());
+        data
+    }
+
+    /// Trading price of 1_050 (expo -2) published a minute after `DEADLINE`, 0.1% confidence.
+    fn good_buffer() -> Vec<u8> {
+        pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 60, 105_000, 105, PYTH_STATUS_TRADING)
+    }
+
+    fn resolver(comparator: Comparator) -> FeedResolver {
+        FeedResolver {
+            feed: Pubkey::new_unique(),
+            comparator,
+            threshold: 100_000,
+            expo: -2,
+            max_staleness_secs: 300,
+            max_conf_bps: 50,
+        }
+    }
+
+    fn resolve(data: &[u8], r: &FeedResolver) -> Result<Outcome> {
+        feed_outcome(r, &read_pyth_price(data)?, DEADLINE)
+    }
+
+    #[test]
+    fn pyth_program_id_matches() {
+        assert!(PYTH_PROGRAM_ID.to_string() == "FsJ3A3u2vn5cTVofAjvy6y5kwABJAqYWpe4975bi2epH");
+    }
+
+    #[test]
+    fn feed_resolves_by_comparator() {
+        let data = good_buffer();
+        assert!(resolve(&data, &resolver(Comparator::Above)) == Ok(Outcome::Hit));
+        assert!(resolve(&data, &resolver(Comparator::AtOrBelow)) == Ok(Outcome::Miss));
+        let mut at = resolver(Comparator::AtOrAbove);
+        at.threshold = 105_000;
+        assert!(resolve(&data, &at) == Ok(Outcome::Hit));
+        at.comparator = Comparator::Below;
+        assert!(resolve(&data, &at) == Ok(Outcome::Miss));
+    }
+
+    #[test]
+    fn feed_with_wrong_magic_or_short_data_is_rejected() {
+        let data = pyth_buffer(0xdead_beef, -2, DEADLINE + 60, 105_000, 105, PYTH_STATUS_TRADING);
+        assert!(read_pyth_price(&data).err() == Some(AmmError::InvalidFeed.into()));
+        assert!(read_pyth_price(&good_buffer()[..200]).err() == Some(AmmError::InvalidFeed.into()));
+    }
+
+    #[test]
+    fn feed_not_trading_is_rejected() {
+        let data = pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 60, 105_000, 105, 2);
+        assert!(read_pyth_price(&data).err() == Some(AmmError::InvalidFeed.into()));
+    }
+
+    #[test]
+    fn stale_feed_is_rejected() {
+        let r = resolver(Comparator::Above);
+        let late = pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 301, 105_000, 105, PYTH_STATUS_TRADING);
+        assert!(resolve(&late, &r) == Err(AmmError::StaleFeed.into()));
+        let last = pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 300, 105_000, 105, PYTH_STATUS_TRADING);
+        assert!(resolve(&last, &r).is_ok());
+        let before_deadline = pyth_buffer(PYTH_MAGIC, -2, DEADLINE - 1, 105_000, 105, PYTH_STATUS_TRADING);
+        let mut lax = r;
+        lax.max_staleness_secs = 10_000;
+        assert!(resolve(&before_deadline, &lax) == Err(AmmError::StaleFeed.into()));
+    }
+
+    #[test]
+    fn wide_confidence_is_rejected() {
+        let data = pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 60, 105_000, 526, PYTH_STATUS_TRADING);
+        let r = resolver(Comparator::Above);
+        assert!(resolve(&data, &r) == Err(AmmError::FeedConfidenceTooWide.into()));
+        let edge = pyth_buffer(PYTH_MAGIC, -2, DEADLINE + 60, 105_000, 525, PYTH_STATUS_TRADING);
+        assert!(resolve(&edge, &r) == Ok(Outcome::Hit));
+    }
+
+    #[test]
+    fn expo_mismatch_is_rejected() {
+        let data = pyth_buffer(PYTH_MAGIC, -8, DEADLINE + 60, 105_000, 105, PYTH_STATUS_TRADING);
+        assert!(resolve(&data, &resolver(Comparator::Above)) == Err(AmmError::InvalidFeed.into()));
+    }
+
+    const B: i128 = 100 * FP_SCALER;
+
+    /// Unresolved market with b = 100 USDC on a flat curve and nothing traded.
+    fn market() -> Market {
+        Market {
+            authority: Pubkey::default(),
+            usdc_mint: Pubkey::default(),
+            vault_usdc: Pubkey::default(),
+            b_fp: B,
+            fee_bps: 0,
+            deadline_ts: DEADLINE,
+            grace_period_secs: 0,
+            outcome: Outcome::Unresolved,
+            q_hit_fp: 0,
+            q_miss_fp: 0,
+            paused: false,
+            max_trade_usdc_fp: i128::MAX,
+            max_position_shares_fp: i128::MAX,
+            treasury: None,
+            milestone_id: Vec::new(),
+            liquidity_usdc_fp: subsidy_for_b(B),
+            oracle_signer: None,
+            bump: 0,
+            max_sell_shares_fp: i128::MAX,
+            max_sell_usdc_fp: i128::MAX,
+            max_side_shares_fp: 0,
+            max_imbalance_fp: 0,
+            min_trade_interval_secs: 0,
+            max_trades_per_window: 0,
+            trade_window_secs: 0,
+            referral_fee_bps: None,
+            fee_model: FeeModel::Flat,
+            accrued_fees_fp: 0,
+            fee_split: FeeSplit::PROTOCOL_ONLY,
+            creator_fees_fp: 0,
+            lp_fees_fp: 0,
+            lp_mint: Pubkey::default(),
+            lp_liquidity_fp: 0,
+            hit_shares_outstanding_fp: 0,
+            miss_shares_outstanding_fp: 0,
+            residual_snapshotted: false,
+            lp_pool_fp: 0,
+            authority_residual_fp: 0,
+            residual_recipient: None,
+            sponsor_liquidity_fp: 0,
+            sponsor_unclaimed_fp: 0,
+            sponsor_pool_fp: 0,
+            auto_b: false,
+            b_schedule: Vec::new(),
+            oracles: Vec::new(),
+            oracle_threshold: 0,
+            settlement_bond_fp: 0,
+            challenge_window_secs: 0,
+            proposal: None,
+            bond_escrow_fp: 0,
+            void_refund: VoidRefund::CostBasis,
+            cost_basis_outstanding_fp: 0,
+            hit_payout_fp: 0,
+            attestation_nonce: 0,
+            settlement_timeout_secs: 0,
+            force_voided: false,
+            early_resolution: false,
+            early_resolution_ts: 0,
+            early_outcome: Outcome::Unresolved,
+            pending_oracle_update: None,
+            feed_resolver: None,
+            volume_tracked: false,
+            void_payout_ratio_fp: FP_SCALER,
+        }
+    }
+
+    /// `market()` after buyers took the HIT price to `p_hit`, with a vault holding exactly
+    /// the initial b·ln 2 subsidy plus what they paid (i.e. C(q)).
+    fn funded_market(p_hit: f64) -> (Market, u64) {
+        let mut m = market();
+        let q_hit = ((B as f64) * (p_hit / (1.0 - p_hit)).ln()).round() as i128;
+        m.q_hit_fp = q_hit;
+        m.hit_shares_outstanding_fp = q_hit;
+        let vault = lmsr_cost(B, q_hit, 0).unwrap() as u64;
+        (m, vault)
+    }
+
+    fn close(a: i128, b: f64) -> bool {
+        (a as f64 - b).abs() <= 2.0
+    }
+
+    #[test]
+    fn funded_curve_is_exactly_solvent() {
+        for p in [0.5, 0.95] {
+            let (m, vault) = funded_market(p);
+            assert!(close(solvency_margin_fp(&m, vault).unwrap(), 0.0));
+            // Fee buckets and bonds are not collateral
+            let mut held = m;
+            held.lp_fees_fp = 5;
+            held.bond_escrow_fp = 7;
+            assert!(close(solvency_margin_fp(&held, vault).unwrap(), -12.0));
+        }
+    }
+
+    #[test]
+    fn raising_b_tops_up_the_worst_case_loss_at_the_current_price() {
+        for p in [0.5, 0.95] {
+            let (mut m, vault) = funded_market(p);
+            rescale_curve(&mut m, 2 * B).unwrap();
+            assert!((lmsr_price_hit(m.b_fp, m.q_hit_fp, m.q_miss_fp).unwrap() - p).abs() < 1e-6);
+            let top_up = b_change_subsidy_fp(&m, B, vault).unwrap();
+            // Δb·ln(1/min(p, 1−p)): ln 2 only at p = 0.5
+            assert!(close(top_up, (B as f64) * (1.0 / p.min(1.0 - p)).ln()));
+            assert!(solvency_margin_fp(&m, vault + top_up as u64).unwrap() >= -2);
+        }
+        let (mut m, vault) = funded_market(0.95);
+        rescale_curve(&mut m, 2 * B).unwrap();
+        assert!(b_change_subsidy_fp(&m, B, vault).unwrap() > 4 * subsidy_for_b(B));
+    }
+
+    #[test]
+    fn sponsor_deposit_funds_its_b_raise_at_any_price() {
+        let deposit = 10 * FP_SCALER;
+        for p in [0.5, 0.95] {
+            let (mut m, vault) = funded_market(p);
+            let db = b_raise_for_deposit(&m, deposit).unwrap();
+            assert!(close(db, deposit as f64 / (1.0 / p.min(1.0 - p)).ln()));
+            rescale_curve(&mut m, B + db).unwrap();
+            assert!(close(solvency_margin_fp(&m, vault + deposit as u64).unwrap(), 0.0));
+        }
+        // A liquidity / ln 2 raise would have left the skewed market short
+        let (mut m, vault) = funded_market(0.95);
+        rescale_curve(&mut m, max_b_for_liquidity(subsidy_for_b(B) + deposit)).unwrap();
+        assert!(solvency_margin_fp(&m, vault + deposit as u64).unwrap() < -FP_SCALER);
+    }
+
+    #[test]
+    fn covered_b_accounts_for_the_current_price() {
+        let deposit = 10 * FP_SCALER;
+        for p in [0.5, 0.95] {
+            let (m, vault) = funded_market(p);
+            assert!((max_covered_b_fp(&m, vault).unwrap() - B).abs() < 1_000);
+            let extra = (deposit as f64) / (1.0 / p.min(1.0 - p)).ln();
+            let covered = max_covered_b_fp(&m, vault + deposit as u64).unwrap();
+            assert!((covered as f64 - (B as f64 + extra)).abs() < 1_000.0);
+        }
+        // Flat curve: the auto-b target liquidity / ln 2 is exactly what is covered
+        let (m, vault) = funded_market(0.5);
+        let covered = max_covered_b_fp(&m, vault + deposit as u64).unwrap();
+        assert!((covered - max_b_for_liquidity(m.liquidity_usdc_fp + deposit)).abs() < 1_000);
+    }
+
+    #[test]
+    fn lowering_b_releases_only_what_stays_covered() {
+        // Flat curve: the whole b·ln 2 difference is spare
+        let (mut m, vault) = funded_market(0.5);
+        rescale_curve(&mut m, B / 2).unwrap();
+        let release = b_change_subsidy_fp(&m, B, vault).unwrap();
+        assert!(release == -(subsidy_for_b(B) - subsidy_for_b(B / 2)));
+
+        // At p = 0.95 the HIT shares already owed keep almost all of it in the vault
+        let (mut m, vault) = funded_market(0.95);
+        rescale_curve(&mut m, B / 2).unwrap();
+        let release = b_change_subsidy_fp(&m, B, vault).unwrap();
+        assert!(close(release, -(B as f64) / 2.0 * (1.0 / 0.95f64).ln()));
+        assert!(solvency_margin_fp(&m, vault - (-release) as u64).unwrap() >= 0);
+
+        // Nothing spare, nothing released
+        let (mut m, vault) = funded_market(0.95);
+        m.creator_fees_fp = vault as i128;
+        rescale_curve(&mut m, B / 2).unwrap();
+        assert!(b_change_subsidy_fp(&m, B, vault).unwrap() == 0);
+    }
+
+    #[test]
+    fn oracle_quorum_needs_a_strict_majority() {
+        let keys: Vec<Pubkey> = (0..MAX_ORACLES).map(|_| Pubkey::new_unique()).collect();
+        assert!(validate_oracle_quorum(&[], 0).is_ok());
+        assert!(validate_oracle_quorum(&keys, 4).is_ok());
+        assert!(validate_oracle_quorum(&keys, 3).is_err());
+        assert!(validate_oracle_quorum(&keys, 1).is_err());
+        assert!(validate_oracle_quorum(&keys[..4], 2).is_err());
+        assert!(validate_oracle_quorum(&keys[..4], 3).is_ok());
+        assert!(validate_oracle_quorum(&[keys[0], keys[0], keys[1]], 2).is_err());
+        assert!(validate_oracle_quorum(&keys, 8).is_err());
+    }
+
+    #[test]
+    fn void_shortfall_is_shared_pro_rata() {
+        assert!(void_payout_ratio_fp(500, 0).unwrap() == FP_SCALER);
+        assert!(void_payout_ratio_fp(1_500, 1_000).unwrap() == FP_SCALER);
+        assert!(void_payout_ratio_fp(-5, 1_000).unwrap() == 0);
+
+        // Vault holds 60 for 100 of cost basis plus 10 of unwithdrawn fees
+        let mut m = market();
+        m.outcome = Outcome::Void;
+        m.cost_basis_outstanding_fp = 100 * FP_SCALER;
+        m.accrued_fees_fp = 10 * FP_SCALER;
+        m.lp_liquidity_fp = 50 * FP_SCALER;
+        m.liquidity_usdc_fp = 50 * FP_SCALER;
+        let vault = (70 * FP_SCALER) as u64;
+        m.snapshot_residual(vault).unwrap();
+        assert!(m.void_payout_ratio_fp == FP_SCALER * 6 / 10);
+        assert!(m.lp_pool_fp == 0 && m.authority_residual_fp == 0);
+
+        // The first and last redeemer take the same haircut
+        let first = scale_void_refund_fp(30 * FP_SCALER, m.void_payout_ratio_fp).unwrap();
+        let last = scale_void_refund_fp(70 * FP_SCALER, m.void_payout_ratio_fp).unwrap();
+        assert!(first == 18 * FP_SCALER && last == 42 * FP_SCALER);
+        assert!(first + last + m.accrued_fees_fp == vault as i128);
+        m.cost_basis_outstanding_fp -= 30 * FP_SCALER;
+        assert!(m.redemption_obligations_fp().unwrap() == last);
+    }
+
+    #[test]
+    fn fee_split_pays_the_referrer_first_and_lps_the_remainder() {
+        let split = FeeSplit { protocol_bps: 5_000, creator_bps: 3_000, lp_bps: 2_000 };
+        let referrer = Some(Pubkey::new_unique());
+        let (routed, protocol) = split_fee(FP_SCALER, 2_000, referrer, split).unwrap();
+        assert!(routed.referrer == referrer && routed.referral_fp == 200_000);
+        assert!(protocol == 400_000 && routed.creator_fp == 240_000 && routed.lp_fp == 160_000);
+
+        // No referrer, no referral cut whatever the rate
+        let (routed, protocol) = split_fee(FP_SCALER, 2_000, None, split).unwrap();
+        assert!(routed.referral_fp == 0 && protocol == 500_000 && routed.lp_fp == 200_000);
+
+        // Rounding dust goes to LPs, so the parts always add up to the fee
+        let thirds = FeeSplit { protocol_bps: 3_333, creator_bps: 3_333, lp_bps: 3_334 };
+        let (routed, protocol) = split_fee(7, 0, None, thirds).unwrap();
+        assert!(protocol == 2 && routed.creator_fp == 2 && routed.lp_fp == 3);
+
+        let (routed, protocol) = split_fee(FP_SCALER, 0, None, FeeSplit::PROTOCOL_ONLY).unwrap();
+        assert!(protocol == FP_SCALER && routed.creator_fp == 0 && routed.lp_fp == 0);
+    }
+
+    #[test]
+    fn volume_rolls_over_a_thirty_day_window() {
+        const DAY0: i64 = 20_000;
+        let at = |day: i64| day * SECS_PER_DAY + 3_600;
+        let mut v = TraderVolume {
+            owner: Pubkey::new_unique(),
+            bump: 0,
+            last_day: DAY0,
+            daily_volume_fp: [0; VOLUME_WINDOW_DAYS],
+        };
+        v.record(at(DAY0), 100);
+        v.record(at(DAY0), 20);
+        v.record(at(DAY0 + 1), 50);
+        assert!(v.rolling_volume_fp(at(DAY0 + 1)) == 170);
+        // DAY0 ages out once it is thirty days old, even before anything new is recorded
+        assert!(v.rolling_volume_fp(at(DAY0 + 29)) == 170);
+        assert!(v.rolling_volume_fp(at(DAY0 + 30)) == 50);
+        // DAY0 + 30 reuses DAY0's bucket, which must start from zero
+        v.record(at(DAY0 + 30), 7);
+        assert!(v.rolling_volume_fp(at(DAY0 + 30)) == 57);
+        // A long gap clears every bucket
+        v.record(at(DAY0 + 100), 3);
+        assert!(v.rolling_volume_fp(at(DAY0 + 100)) == 3);
+    }
+
+    #[test]
+    fn residual_is_split_once_and_stays_put() {
+        let mut m = market();
+        m.outcome = Outcome::Hit;
+        m.hit_shares_outstanding_fp = 40 * FP_SCALER;
+        m.miss_shares_outstanding_fp = 70 * FP_SCALER;
+        m.accrued_fees_fp = 5 * FP_SCALER;
+        m.creator_fees_fp = 5 * FP_SCALER;
+        m.lp_fees_fp = 6 * FP_SCALER;
+        m.liquidity_usdc_fp = 100 * FP_SCALER;
+        m.lp_liquidity_fp = 50 * FP_SCALER;
+        m.sponsor_liquidity_fp = 25 * FP_SCALER;
+        let vault = (150 * FP_SCALER) as u64;
+
+        // Surplus 100: LPs take their fees plus half the other 94, sponsors a quarter
+        m.snapshot_residual(vault).unwrap();
+        assert!(m.lp_pool_fp == 53 * FP_SCALER);
+        assert!(m.sponsor_pool_fp == 23_500_000 && m.authority_residual_fp == 23_500_000);
+
+        // A redemption shrinks the vault and the obligations alike, and a later call
+        // does not re-split
+        m.hit_shares_outstanding_fp -= 10 * FP_SCALER;
+        let vault = vault - (10 * FP_SCALER) as u64;
+        assert!(m.residual_surplus_fp(vault).unwrap() == 100 * FP_SCALER);
+        m.snapshot_residual(vault / 2).unwrap();
+        assert!(m.lp_pool_fp == 53 * FP_SCALER && m.authority_residual_fp == 23_500_000);
+    }
+}
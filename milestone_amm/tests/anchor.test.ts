@@ -4,6 +4,7 @@
 describe("Milestone AMM", () => {
   const SEED_MARKET = Buffer.from("market");
   const SEED_POSITION = Buffer.from("position");
+  const SEED_LP_MINT = Buffer.from("lp_mint");
   const DECIMALS = 6; // USDC-style
   const ONE = 10 ** DECIMALS;
 
@@ -123,6 +124,11 @@ describe("Milestone AMM", () => {
       spl.ASSOCIATED_TOKEN_PROGRAM_ID
     );
 
+    const [lpMintPda] = web3.PublicKey.findProgramAddressSync(
+      [SEED_LP_MINT, marketPda.toBuffer()],
+      pg.program.programId
+    );
+
     // 3) init_market
     const now = Math.floor(Date.now() / 1000);
     const params = {
@@ -132,7 +138,14 @@ describe("Milestone AMM", () => {
       gracePeriodSecs: new BN(300),   // 5 min
       maxTradeUsdcFp: new BN(200 * ONE), // 200 USDC per trade
       maxPositionSharesFp: new BN(10_000 * ONE), // large cap
+      maxSellSharesFp: new BN(10_000 * ONE), // large cap
+      maxSellUsdcFp: new BN(200 * ONE), // 200 USDC per sell
       treasury: null,
+      feeModel: { flat: {} },
+      feeSplit: null, // config default, else 100% protocol
+      autoB: false,
+      voidRefund: { costBasis: {} },
+      settlementTimeoutSecs: new BN(30 * 24 * 3600),
     };
 
     // Call init_market (creates market account + vault ATA)
@@ -143,6 +156,8 @@ describe("Milestone AMM", () => {
         usdcMint,
         vaultUsdc: vaultUsdcAta,
         market: marketPda,
+        lpMint: lpMintPda,
+        config: null,
         systemProgram: web3.SystemProgram.programId,
         associatedTokenProgram: spl.ASSOCIATED_TOKEN_PROGRAM_ID,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
@@ -192,7 +207,10 @@ describe("Milestone AMM", () => {
         userUsdc: userUsdcAta,
         vaultUsdc: vaultUsdcAta,
         position: positionPda,
-        treasuryUsdc: vaultUsdcAta, // not used since treasury=null; still pass a writable token account
+        treasuryUsdc: null, // treasury=null, so no treasury account is needed
+        config: null,
+        referrerUsdc: null,
+        traderVolume: null,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })
@@ -217,7 +235,10 @@ describe("Milestone AMM", () => {
         userUsdc: userUsdcAta,
         vaultUsdc: vaultUsdcAta,
         position: positionPda,
-        treasuryUsdc: vaultUsdcAta, // again, passed but unused without treasury
+        treasuryUsdc: null,
+        config: null,
+        referrerUsdc: null,
+        traderVolume: null,
         tokenProgram: spl.TOKEN_PROGRAM_ID,
         systemProgram: web3.SystemProgram.programId,
       })